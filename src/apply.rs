@@ -0,0 +1,380 @@
+// Author: Jorge Alarcon Alvarez
+// Email:  jorge4larcon@gmail.com
+// This module is used to bring the settings of a server to a desired state, only the settings
+// that differ are set.
+
+extern crate clap;
+
+use crate::commands;
+use crate::server_config;
+use crate::targets;
+use std::convert::TryFrom;
+use std::fmt;
+use std::io;
+use std::io::BufRead;
+use std::io::Write;
+use std::path;
+
+#[derive(Clone)]
+pub struct Change {
+    pub setting: server_config::Setting,
+    pub current: serde_json::Value,
+    pub desired: serde_json::Value
+}
+
+impl Change {
+    pub fn command(&self) -> Option<commands::Command> {
+        set_command(self.setting, &self.desired)
+    }
+
+    fn send(&self, target: &targets::Target) -> Result<Applied, String> {
+        let command = self.command().ok_or(format!("{} can not be set to {}", self.setting, self.desired))?;
+        match target.command(command).send_and_parse() {
+            Ok(reply) => {
+                let dropped_clients = reply.get("dropped_clients").and_then(|dropped| dropped.as_array()).map(|dropped| dropped.len());
+                Ok(Applied { change: self.clone(), dropped_clients })
+            },
+            Err(e) => Err(format!("{} could not be set: {}", self.setting, e))
+        }
+    }
+}
+
+fn set_command(setting: server_config::Setting, value: &serde_json::Value) -> Option<commands::Command> {
+    let set = match setting {
+        server_config::Setting::Capacity => commands::Set::Capacity(u16::try_from(value.as_u64()?).ok()?),
+        server_config::Setting::ListSize => commands::Set::ListSize(u16::try_from(value.as_u64()?).ok()?),
+        server_config::Setting::DropVotes => commands::Set::DropVotes(u8::try_from(value.as_u64()?).ok()?),
+        server_config::Setting::DropVerification => commands::Set::DropVerification(value.as_bool()?),
+        server_config::Setting::Password | server_config::Setting::Key => return None
+    };
+    Some(commands::Command::Set(set))
+}
+
+pub struct ApplyOptions {
+    // Do not ask before changing a server
+    pub assume_yes: bool,
+    pub dry_run: bool,
+    // Undo the changes already made when one of them fails
+    pub transactional: bool
+}
+
+impl ApplyOptions {
+    pub fn from_clap_matches(subcommand_matches: &clap::ArgMatches) -> ApplyOptions {
+        ApplyOptions {
+            assume_yes: subcommand_matches.is_present("yes"),
+            dry_run: subcommand_matches.is_present("dry-run"),
+            transactional: subcommand_matches.is_present("transactional")
+        }
+    }
+}
+
+// A Set the server accepted
+pub struct Applied {
+    pub change: Change,
+    // The clients a drop-votes change dropped
+    pub dropped_clients: Option<usize>
+}
+
+impl fmt::Display for Applied {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {} -> {} ok", self.change.setting, self.change.current, self.change.desired)?;
+        match self.dropped_clients {
+            Some(dropped_clients) => write!(f, ", {} client(s) dropped", dropped_clients),
+            None => Ok(())
+        }
+    }
+}
+
+// What executing a plan did, the error of every Set that failed
+pub struct Outcome {
+    pub applied: Vec<Applied>,
+    pub error: Option<String>
+}
+
+impl Outcome {
+    pub fn changed(&self) -> Vec<server_config::Setting> {
+        self.applied.iter().map(|applied| applied.change.setting).collect()
+    }
+}
+
+// The Sets that take a server from its running config to the desired one
+pub struct Plan {
+    pub changes: Vec<Change>
+}
+
+impl Plan {
+    pub fn new(current: &server_config::ServerConfig, desired: &server_config::DesiredConfig) -> Plan {
+        let changes = desired.settings().into_iter()
+            .filter_map(|setting| {
+                let desired = desired.get(setting)?;
+                let current = current.get(setting);
+                if current == desired {
+                    return None;
+                }
+                Some(Change { setting, current, desired })
+            })
+            .collect();
+        Plan { changes }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    pub fn render(&self) -> String {
+        if self.is_empty() {
+            return String::from("Nothing to change");
+        }
+        let mut lines: Vec<String> = self.changes.iter()
+            .map(|change| format!("  {}: {} -> {}", change.setting, change.current, change.desired))
+            .collect();
+        // The server drops the clients that reached the new number of votes
+        if let Some(change) = self.changes.iter().find(|change| change.setting == server_config::Setting::DropVotes) {
+            lines.push(format!("  warning: the clients with {} or more drop votes will be dropped and can not be brought back", change.desired));
+        }
+        format!("{} change(s):\n{}", self.changes.len(), lines.join("\n"))
+    }
+
+    // Stops at the first Set that fails, the ones after it are not sent
+    pub fn execute(&self, target: &targets::Target) -> Outcome {
+        let mut applied = Vec::new();
+        for change in &self.changes {
+            match change.send(target) {
+                Ok(change_applied) => applied.push(change_applied),
+                Err(e) => {
+                    let names: Vec<&str> = applied.iter().map(|applied| applied.change.setting.name()).collect();
+                    let names = if names.is_empty() { String::from("none") } else { names.join(", ") };
+                    let error = format!("{}, the settings changed before it: {}", e, names);
+                    return Outcome { applied, error: Some(error) };
+                }
+            }
+        }
+        Outcome { applied, error: None }
+    }
+
+    // Sends every Set even when one fails, so a roll back brings back as much as it can
+    pub fn execute_all(&self, target: &targets::Target) -> Outcome {
+        let mut applied = Vec::new();
+        let mut errors = Vec::new();
+        for change in &self.changes {
+            match change.send(target) {
+                Ok(change_applied) => applied.push(change_applied),
+                Err(e) => errors.push(e)
+            }
+        }
+        let error = if errors.is_empty() { None } else { Some(errors.join("\n")) };
+        Outcome { applied, error }
+    }
+
+    // The Sets that bring back the values the settings had before the plan, the last one first.
+    // The running config is read again because a Set that failed, e.g. the connection was closed
+    // before the reply, may have been run anyway.
+    pub fn roll_back(&self, target: &targets::Target) -> Result<Plan, String> {
+        let running = target.running_config()?;
+        let changes = self.changes.iter().rev()
+            .filter_map(|change| {
+                let current = running.get(change.setting);
+                if current == change.current {
+                    return None;
+                }
+                Some(Change { setting: change.setting, current, desired: change.current.clone() })
+            })
+            .collect();
+        Ok(Plan { changes })
+    }
+
+    // The settings of the plan as the server has them now
+    pub fn final_state(&self, target: &targets::Target) -> String {
+        match target.running_config() {
+            Ok(config) => {
+                let settings: Vec<String> = self.changes.iter()
+                    .map(|change| format!("{} = {}", change.setting, config.get(change.setting)))
+                    .collect();
+                format!("final state: {}", settings.join(", "))
+            },
+            Err(e) => format!("the final state is unknown: {}", e)
+        }
+    }
+}
+
+// Asks in the terminal, anything but y or yes is a no
+pub fn confirm(question: &str) -> bool {
+    print!("{} [y/N] ", question);
+    let _ = io::stdout().flush();
+    let mut answer = String::new();
+    if io::stdin().lock().read_line(&mut answer).is_err() {
+        return false;
+    }
+    matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")
+}
+
+// Shows the plan of a server and executes it when it is confirmed. Returns whether it went well.
+pub fn apply_to(target: &targets::Target, desired: &server_config::DesiredConfig, options: &ApplyOptions) -> bool {
+    let current = match target.running_config() {
+        Ok(current) => current,
+        Err(e) => {
+            eprintln!("{}: {}", target.name, e);
+            return false;
+        }
+    };
+    let plan = Plan::new(&current, desired);
+    println!("{}: {}", target.name, plan.render());
+    if plan.is_empty() || options.dry_run {
+        return true;
+    }
+    if !options.assume_yes && !confirm(&format!("Apply the changes to {}?", target.name)) {
+        println!("{}: nothing was changed", target.name);
+        return true;
+    }
+    let outcome = plan.execute(target);
+    for applied in &outcome.applied {
+        println!("{}: {}", target.name, applied);
+    }
+    match outcome.error {
+        None => {
+            println!("{}: {} setting(s) changed", target.name, outcome.applied.len());
+            true
+        },
+        Some(e) => {
+            eprintln!("{}: {}", target.name, e);
+            if options.transactional {
+                roll_back(target, &plan);
+            }
+            false
+        }
+    }
+}
+
+fn roll_back(target: &targets::Target, plan: &Plan) {
+    let roll_back_plan = match plan.roll_back(target) {
+        Ok(roll_back_plan) => roll_back_plan,
+        Err(e) => {
+            eprintln!("{}: the changes could not be rolled back: {}", target.name, e);
+            return;
+        }
+    };
+    println!("{}: rolling back {} setting(s)", target.name, roll_back_plan.changes.len());
+    let outcome = roll_back_plan.execute_all(target);
+    for applied in &outcome.applied {
+        println!("{}: {} rolled back", target.name, applied.change.setting);
+    }
+    if let Some(e) = outcome.error {
+        eprintln!("{}: {}", target.name, e);
+    }
+    if roll_back_plan.changes.iter().any(|change| change.setting == server_config::Setting::DropVotes) {
+        log::warn!(server = target.name.as_str(); "The clients dropped when drop-votes changed are not restored");
+    }
+    println!("{}: {}", target.name, plan.final_state(target));
+}
+
+// The exit code is 1 when a server could not be read or changed
+pub fn apply(matches: &clap::ArgMatches) -> i32 {
+    let subcommand_matches = match matches.subcommand_matches("apply") {
+        Some(subcommand_matches) => subcommand_matches,
+        None => return 1
+    };
+    let desired = match subcommand_matches.value_of("file").map(|file| server_config::DesiredConfig::load(path::Path::new(file))) {
+        Some(Ok(desired)) => desired,
+        Some(Err(e)) => {
+            eprintln!("{}", e);
+            return 1;
+        },
+        None => return 1
+    };
+    let targets = match targets::from_clap_matches(matches) {
+        Ok(targets) if !targets.is_empty() => targets,
+        Ok(_) => {
+            eprintln!("Give at least one server or a group");
+            return 1;
+        },
+        Err(e) => {
+            eprintln!("{}", e);
+            return 1;
+        }
+    };
+    let options = ApplyOptions::from_clap_matches(subcommand_matches);
+    let mut failed = false;
+    for target in &targets {
+        failed |= !apply_to(target, &desired, &options);
+    }
+    if failed { 1 } else { 0 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clients;
+    use crate::ipparser;
+    use crate::simulator;
+
+    fn desired(contents: &str) -> server_config::DesiredConfig {
+        server_config::DesiredConfig::from_toml_str(contents).unwrap()
+    }
+
+    #[test]
+    fn nothing_to_change() {
+        let current = server_config::ServerConfig::default();
+        assert!(Plan::new(&current, &server_config::DesiredConfig::default()).is_empty());
+        assert!(Plan::new(&current, &server_config::DesiredConfig::from_server_config(&current)).is_empty());
+        let plan = Plan::new(&current, &desired("capacity = 100\ndrop-verification = true\n"));
+        assert!(plan.is_empty());
+        assert_eq!(plan.render(), "Nothing to change");
+    }
+
+    #[test]
+    fn only_the_settings_that_differ() {
+        let current = server_config::ServerConfig::default();
+        let plan = Plan::new(&current, &desired("drop-verification = false\ncapacity = 100\nlist-size = 10\ndrop-votes = 2\n"));
+        let changes: Vec<(server_config::Setting, String, String)> = plan.changes.iter()
+            .map(|change| (change.setting, change.current.to_string(), change.desired.to_string()))
+            .collect();
+        assert_eq!(changes, vec![
+            (server_config::Setting::ListSize, String::from("20"), String::from("10")),
+            (server_config::Setting::DropVotes, String::from("3"), String::from("2")),
+            (server_config::Setting::DropVerification, String::from("true"), String::from("false"))
+        ]);
+        assert!(plan.render().starts_with("3 change(s):\n  list-size: 20 -> 10\n"));
+        assert!(plan.render().ends_with("warning: the clients with 2 or more drop votes will be dropped and can not be brought back"));
+    }
+
+    #[test]
+    fn set_commands() {
+        let command = |setting, value: serde_json::Value| set_command(setting, &value).map(|command| command.name());
+        assert_eq!(command(server_config::Setting::Capacity, serde_json::json!(150)), Some("set-capacity"));
+        assert_eq!(command(server_config::Setting::Capacity, serde_json::json!(70000)), None);
+        assert_eq!(command(server_config::Setting::DropVotes, serde_json::json!(256)), None);
+        assert_eq!(command(server_config::Setting::ListSize, serde_json::json!(-1)), None);
+        assert_eq!(command(server_config::Setting::DropVerification, serde_json::json!("false")), None);
+        assert_eq!(command(server_config::Setting::Key, serde_json::json!("admin")), None);
+    }
+
+    #[test]
+    fn roll_back_what_the_server_has() {
+        let simulator = simulator::Simulator::bind("127.0.0.1:0", simulator::SimState::new(clients::ClientsMap::default(), server_config::ServerConfig::default())).unwrap();
+        let address = simulator.address().unwrap();
+        let state = simulator.state();
+        simulator.spawn();
+        let target = targets::Target {
+            name: address.clone(),
+            password: String::from("admin"),
+            address: ipparser::ServerAddress::new_from_str(&address).unwrap(),
+            options: commands::ConnectionOptions::default(),
+            profile: None
+        };
+        let plan = Plan::new(&server_config::ServerConfig::default(), &desired("capacity = 150\nlist-size = 10\n"));
+        // Only the capacity got to the server, the list size is as it was
+        state.lock().unwrap().config.set_capacity(150).unwrap();
+        let roll_back_plan = plan.roll_back(&target).unwrap();
+        assert_eq!(roll_back_plan.changes.len(), 1);
+        let change = &roll_back_plan.changes[0];
+        assert_eq!((change.setting, change.current.to_string(), change.desired.to_string()),
+                   (server_config::Setting::Capacity, String::from("150"), String::from("100")));
+        // Everything changed, undone the last one first
+        state.lock().unwrap().config.set_list_size(10);
+        let settings: Vec<server_config::Setting> = plan.roll_back(&target).unwrap().changes.iter().map(|change| change.setting).collect();
+        assert_eq!(settings, vec![server_config::Setting::ListSize, server_config::Setting::Capacity]);
+        roll_back(&target, &plan);
+        let config = &state.lock().unwrap().config;
+        assert_eq!((config.capacity(), config.list_size()), (100, 20));
+    }
+}
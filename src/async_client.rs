@@ -0,0 +1,153 @@
+// Author: Jorge Alarcon Alvarez
+// Email:  jorge4larcon@gmail.com
+// This module is used to send the commands without blocking a thread, so many servers can be
+// administered at the same time. The connection is opened in the blocking pool by the same code
+// as the blocking client, only the TLS handshake and the requests and replies are async. It needs
+// a tokio runtime with the time driver enabled.
+
+extern crate tokio;
+extern crate tokio_rustls;
+
+use crate::auth;
+use crate::commands;
+use crate::tls;
+use crate::transport;
+use std::future::Future;
+use std::io;
+use std::time;
+use tokio::io::{
+    AsyncRead,
+    AsyncReadExt,
+    AsyncWrite,
+    AsyncWriteExt
+};
+
+trait AsyncStream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<S: AsyncRead + AsyncWrite + Unpin + Send> AsyncStream for S {}
+
+// The request and the reply are the same ones of send_and_interpret, only the waiting changes
+impl commands::BaseCommand {
+    pub async fn send_and_interpret_async(&self) -> String {
+        self.interpret(self.send_async().await)
+    }
+
+    async fn send_async(&self) -> Option<String> {
+        let retries = if self.command().is_idempotent() { self.options().retries } else { 0 };
+        for retry in 0..=retries {
+            if retry > 0 {
+                let backoff = self.options().backoff(retry);
+                log::warn!(server:% = self.address(), command = self.command().name(); "Retrying in {} ms [{} of {}]", backoff.as_millis(), retry, retries);
+                tokio::time::sleep(backoff).await;
+            }
+            if let Some(mut stream) = self.open_stream().await {
+                if let Some(reply) = self.exchange_async(stream.as_mut()).await {
+                    return Some(reply);
+                }
+            }
+        }
+        None
+    }
+
+    async fn exchange_async(&self, stream: &mut dyn AsyncStream) -> Option<String> {
+        match self.options().auth {
+            commands::AuthMode::Password => self.round_trip_async(stream, &self.to_json_string()).await,
+            commands::AuthMode::Challenge => {
+                let reply = self.round_trip_async(stream, auth::CHALLENGE_REQUEST).await?;
+                match self.challenge_response(&reply) {
+                    Some(signed_request) => self.round_trip_async(stream, &signed_request).await,
+                    None => Some(reply)
+                }
+            }
+        }
+    }
+
+    async fn round_trip_async(&self, stream: &mut dyn AsyncStream, request: &str) -> Option<String> {
+        let address = self.address();
+        let command = self.command().name();
+        let timeout = self.options().timeout;
+        let started = time::Instant::now();
+        log::debug!("request:\n{}", request);
+        let sent = with_timeout(timeout, async {
+            stream.write_all(request.as_bytes()).await?;
+            stream.flush().await
+        }).await;
+        if let Err(e) = sent {
+            log::error!(server:% = address, command; "The request could not be sent to {}: {}", address, e);
+            return None;
+        }
+        log::info!(server:% = address, command; "The request was sent succesfully [{} byte(s)]", request.len());
+        match with_timeout(timeout, receive_async(stream)).await {
+            Ok(reply) => {
+                let latency_ms = started.elapsed().as_millis() as u64;
+                log::info!(server:% = address, command, latency_ms; "{} byte(s) received", reply.len());
+                let reply = String::from_utf8_lossy(&reply).to_string();
+                log::debug!("raw reply received:\n{}", reply);
+                Some(reply)
+            },
+            Err(e) => {
+                log::error!(server:% = address, command; "{} didn't reply anything: {}", address, e);
+                None
+            }
+        }
+    }
+
+    async fn open_stream(&self) -> Option<Box<dyn AsyncStream>> {
+        let (options, address, command) = (self.options().clone(), self.address().clone(), self.command().name());
+        let connection = tokio::task::spawn_blocking(move || options.connect(&address, command)).await.ok()??;
+        let stream: Result<Box<dyn AsyncStream>, String> = match connection {
+            commands::Connection::Tcp(stream, host) => match stream.set_nonblocking(true).and_then(|()| tokio::net::TcpStream::from_std(stream)) {
+                Ok(tcp_stream) => match &self.options().tls {
+                    Some(tls_options) => self.tls_handshake_async(tcp_stream, tls_options, &host).await.map(|tls_stream| Box::new(tls_stream) as Box<dyn AsyncStream>),
+                    None => Ok(Box::new(tcp_stream))
+                },
+                Err(e) => Err(e.to_string())
+            },
+            #[cfg(unix)]
+            commands::Connection::Unix(stream) => match stream.set_nonblocking(true).and_then(|()| tokio::net::UnixStream::from_std(stream)) {
+                Ok(unix_stream) => Ok(Box::new(unix_stream)),
+                Err(e) => Err(e.to_string())
+            }
+        };
+        match stream {
+            Ok(stream) => Some(stream),
+            Err(e) => {
+                log::error!(server:% = self.address(), command; "{}", e);
+                None
+            }
+        }
+    }
+
+    async fn tls_handshake_async(&self, tcp_stream: tokio::net::TcpStream, tls_options: &tls::TlsOptions, host: &str)
+                                 -> Result<tokio_rustls::client::TlsStream<tokio::net::TcpStream>, String> {
+        let server_name = tls_options.server_name(host)?;
+        let name = tls_options.server_name.as_deref().unwrap_or(host);
+        let connector = tokio_rustls::TlsConnector::from(tls_options.client_config()?);
+        let tls_stream = with_timeout(self.options().timeout, connector.connect(server_name, tcp_stream)).await
+            .map_err(|e| format!("TLS handshake with {} failed: {}", name, e))?;
+        tls::log_session(name, tls_stream.get_ref().1.peer_certificates());
+        Ok(tls_stream)
+    }
+}
+
+// Like transport::StreamTransport::receive, the server sends nothing after the reply
+async fn receive_async(stream: &mut dyn AsyncStream) -> io::Result<Vec<u8>> {
+    let mut pending = Vec::new();
+    let mut buffer = vec![0; 4096];
+    loop {
+        if let Some(reply) = transport::split_json(&mut pending, transport::MAX_REPLY_SIZE) {
+            return Ok(reply);
+        }
+        match stream.read(&mut buffer).await? {
+            0 if transport::is_blank(&pending) => {
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "the connection was closed without a reply"));
+            },
+            0 => return Ok(pending),
+            bytes_received => pending.extend_from_slice(&buffer[..bytes_received])
+        }
+    }
+}
+
+async fn with_timeout<T>(duration: time::Duration, future: impl Future<Output = io::Result<T>>) -> io::Result<T> {
+    tokio::time::timeout(duration, future).await.unwrap_or_else(|_| Err(io::Error::from(io::ErrorKind::TimedOut)))
+}
@@ -0,0 +1,326 @@
+// Author: Jorge Alarcon Alvarez
+// Email:  jorge4larcon@gmail.com
+// This module is used to prove the admin password without sending it.
+
+extern crate hmac;
+
+use hmac::Mac;
+use rand::RngCore;
+use std::collections;
+use std::fmt;
+use std::time;
+
+// The admin asks for a nonce with this request and the server replies {"result":"ok","nonce":...}.
+// Then the admin sends {"user":"admin","auth":"hmac-sha256","request":BODY,"hmac":HMAC} in the
+// same connection, where BODY is the usual request without the password, as a string, and HMAC is
+// the hex HMAC-SHA256 of the nonce followed by BODY, keyed by the admin password.
+pub const CHALLENGE_REQUEST: &str = "{\"user\":\"admin\",\"method\":\"challenge\"}";
+pub const CHALLENGE_AUTH: &str = "hmac-sha256";
+const NONCE_SIZE: usize = 32;
+// Signed requests carry these fields, the signature is the hex HMAC-SHA256 of the canonical JSON
+// of the request without the password and the signature, keyed by the admin password
+pub const TIMESTAMP_FIELD: &str = "timestamp";
+pub const NONCE_FIELD: &str = "nonce";
+pub const SIGNATURE_FIELD: &str = "signature";
+const REQUEST_NONCE_SIZE: usize = 16;
+// How old (or how far in the future, the clocks are never in sync) a signed request can be
+pub const MAX_REQUEST_AGE: time::Duration = time::Duration::from_secs(300);
+
+type HmacSha256 = hmac::Hmac<sha2::Sha256>;
+
+pub fn new_nonce() -> String {
+    random_hex(NONCE_SIZE)
+}
+
+pub fn sign(password: &str, nonce: &str, body: &str) -> String {
+    to_hex(&mac(password, &[nonce, body]).finalize().into_bytes())
+}
+
+// The comparison takes the same time wherever the signatures differ
+pub fn verify(password: &str, nonce: &str, body: &str, signature: &str) -> bool {
+    verify_mac(password, &[nonce, body], signature)
+}
+
+// The admin password of a plain request, compared like the signatures so the time taken does not
+// tell how much of it was right, nor its length
+pub fn verify_password(key: &str, password: &str) -> bool {
+    mac(key, &[password]).verify_slice(&mac(key, &[key]).finalize().into_bytes()).is_ok()
+}
+
+fn mac(password: &str, parts: &[&str]) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(password.as_bytes()).expect("HMAC accepts keys of any size");
+    for part in parts {
+        mac.update(part.as_bytes());
+    }
+    mac
+}
+
+fn verify_mac(password: &str, parts: &[&str], signature: &str) -> bool {
+    match from_hex(signature) {
+        Some(signature) => mac(password, parts).verify_slice(&signature).is_ok(),
+        None => false
+    }
+}
+
+fn random_hex(size: usize) -> String {
+    let mut bytes = vec![0; size];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    to_hex(&bytes)
+}
+
+// Compact JSON with the keys of every object sorted, so the client and the server sign the same
+// bytes no matter in which order the fields were written
+pub fn canonical_json(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::Object(fields) => {
+            let mut keys: Vec<&String> = fields.keys().collect();
+            keys.sort();
+            let fields: Vec<String> = keys.iter().map(|key| {
+                format!("{}:{}", serde_json::Value::from(key.as_str()), canonical_json(&fields[key.as_str()]))
+            }).collect();
+            format!("{{{}}}", fields.join(","))
+        },
+        serde_json::Value::Array(values) => {
+            format!("[{}]", values.iter().map(canonical_json).collect::<Vec<String>>().join(","))
+        },
+        other => other.to_string()
+    }
+}
+
+// Adds the timestamp, the nonce and the signature to a request that has no password yet
+pub fn sign_request(request: &mut serde_json::Map<String, serde_json::Value>, password: &str) {
+    request.insert(String::from(TIMESTAMP_FIELD), serde_json::Value::from(unix_time()));
+    request.insert(String::from(NONCE_FIELD), serde_json::Value::from(random_hex(REQUEST_NONCE_SIZE)));
+    let body = canonical_json(&serde_json::Value::Object(request.clone()));
+    request.insert(String::from(SIGNATURE_FIELD), serde_json::Value::from(to_hex(&mac(password, &[&body]).finalize().into_bytes())));
+}
+
+fn unix_time() -> u64 {
+    time::SystemTime::now().duration_since(time::UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+pub fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 || !hex.is_ascii() {
+        return None;
+    }
+    (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok()).collect()
+}
+
+// The server side of the challenge, one per connection. Every nonce is good for one request.
+#[derive(Default)]
+pub struct Challenge {
+    nonce: Option<String>
+}
+
+impl Challenge {
+    pub fn new() -> Challenge {
+        Challenge::default()
+    }
+
+    pub fn is_challenge_request(request: &serde_json::Value) -> bool {
+        request.get("method").and_then(|method| method.as_str()) == Some("challenge")
+    }
+
+    pub fn is_signed_request(request: &serde_json::Value) -> bool {
+        request.get("auth").and_then(|auth| auth.as_str()) == Some(CHALLENGE_AUTH)
+    }
+
+    // The reply to CHALLENGE_REQUEST, a new nonce replaces the one that was not used
+    pub fn issue(&mut self) -> String {
+        let nonce = new_nonce();
+        let reply = serde_json::json!({ "result": "ok", "nonce": nonce }).to_string();
+        self.nonce = Some(nonce);
+        reply
+    }
+
+    // The request inside a signed request when it was signed with the admin password and the
+    // last nonce issued, which can not be used again
+    pub fn open(&mut self, password: &str, signed_request: &serde_json::Value) -> Option<serde_json::Value> {
+        let nonce = self.nonce.take()?;
+        let body = signed_request.get("request")?.as_str()?;
+        let signature = signed_request.get("hmac")?.as_str()?;
+        if !verify(password, &nonce, body, signature) {
+            return None;
+        }
+        serde_json::from_str(body).ok()
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SignatureError {
+    Missing,
+    Invalid,
+    Stale,
+    Reused
+}
+
+impl fmt::Display for SignatureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let description = match self {
+            SignatureError::Missing => "the request is not signed",
+            SignatureError::Invalid => "the signature of the request is not valid",
+            SignatureError::Stale => "the request is too old",
+            SignatureError::Reused => "the nonce of the request was already used"
+        };
+        write!(f, "{}", description)
+    }
+}
+
+// The server side of the signed requests. A nonce is remembered while a request with it would
+// still be fresh, after that the timestamp alone rejects it.
+pub struct ReplayGuard {
+    max_age: u64,
+    seen: collections::HashMap<String, u64>
+}
+
+impl Default for ReplayGuard {
+    fn default() -> Self {
+        ReplayGuard::new(MAX_REQUEST_AGE)
+    }
+}
+
+impl ReplayGuard {
+    pub fn new(max_age: time::Duration) -> ReplayGuard {
+        ReplayGuard { max_age: max_age.as_secs(), seen: collections::HashMap::new() }
+    }
+
+    pub fn is_signed(request: &serde_json::Value) -> bool {
+        request.get(SIGNATURE_FIELD).is_some()
+    }
+
+    pub fn verify(&mut self, password: &str, request: &serde_json::Value) -> Result<(), SignatureError> {
+        self.verify_at(password, request, unix_time())
+    }
+
+    // `now` in seconds since the Unix epoch
+    pub fn verify_at(&mut self, password: &str, request: &serde_json::Value, now: u64) -> Result<(), SignatureError> {
+        let fields = request.as_object().ok_or(SignatureError::Missing)?;
+        let signature = fields.get(SIGNATURE_FIELD).and_then(|s| s.as_str()).ok_or(SignatureError::Missing)?;
+        let timestamp = fields.get(TIMESTAMP_FIELD).and_then(|t| t.as_u64()).ok_or(SignatureError::Missing)?;
+        let nonce = fields.get(NONCE_FIELD).and_then(|n| n.as_str()).ok_or(SignatureError::Missing)?;
+        let mut body = fields.clone();
+        body.remove(SIGNATURE_FIELD);
+        body.remove("password");
+        if !verify_mac(password, &[&canonical_json(&serde_json::Value::Object(body))], signature) {
+            return Err(SignatureError::Invalid);
+        }
+        if timestamp.abs_diff(now) > self.max_age {
+            return Err(SignatureError::Stale);
+        }
+        let max_age = self.max_age;
+        self.seen.retain(|_, seen_timestamp| seen_timestamp.abs_diff(now) <= max_age);
+        if self.seen.contains_key(nonce) {
+            return Err(SignatureError::Reused);
+        }
+        self.seen.insert(nonce.to_string(), timestamp);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PASSWORD: &str = "admin";
+    const NOW: u64 = 1_700_000_000;
+
+    // A set-capacity request signed like sign_request does, with a fixed timestamp
+    fn signed_request(timestamp: u64, nonce: &str) -> serde_json::Value {
+        let mut request = serde_json::json!({ "user": "admin", "method": "set", "what": "capacity", "capacity": 50 });
+        let fields = request.as_object_mut().unwrap();
+        fields.insert(String::from(TIMESTAMP_FIELD), serde_json::Value::from(timestamp));
+        fields.insert(String::from(NONCE_FIELD), serde_json::Value::from(nonce));
+        let body = canonical_json(&serde_json::Value::Object(fields.clone()));
+        fields.insert(String::from(SIGNATURE_FIELD), serde_json::Value::from(to_hex(&mac(PASSWORD, &[&body]).finalize().into_bytes())));
+        request
+    }
+
+    #[test]
+    fn passwords() {
+        assert!(verify_password(PASSWORD, "admin"));
+        assert!(!verify_password(PASSWORD, "admin "));
+        assert!(!verify_password(PASSWORD, "Admin"));
+        assert!(!verify_password(PASSWORD, ""));
+        assert!(verify_password("", ""));
+    }
+
+    #[test]
+    fn hex() {
+        assert_eq!(to_hex(&[0, 1, 0xab, 0xff]), "0001abff");
+        assert_eq!(from_hex("0001abFF"), Some(vec![0, 1, 0xab, 0xff]));
+        assert_eq!(from_hex(""), Some(vec![]));
+        assert_eq!(from_hex("abc"), None);
+        assert_eq!(from_hex("zz"), None);
+        assert_eq!(from_hex("é1"), None);
+    }
+
+    #[test]
+    fn canonical_json_sorts_the_keys() {
+        let value = serde_json::json!({ "what": "key", "b": [{ "z": 1, "a": null }, true], "a": "x\"y" });
+        assert_eq!(canonical_json(&value), "{\"a\":\"x\\\"y\",\"b\":[{\"a\":null,\"z\":1},true],\"what\":\"key\"}");
+        let reordered: serde_json::Value = serde_json::from_str("{\"a\":\"x\\\"y\",\"what\":\"key\",\"b\":[{\"a\":null,\"z\":1},true]}").unwrap();
+        assert_eq!(canonical_json(&reordered), canonical_json(&value));
+    }
+
+    #[test]
+    fn challenge_nonces_are_used_once() {
+        let mut challenge = Challenge::new();
+        let nonce = serde_json::from_str::<serde_json::Value>(&challenge.issue()).unwrap()["nonce"].as_str().unwrap().to_string();
+        let body = "{\"method\":\"get\"}";
+        let signed_request = serde_json::json!({ "auth": CHALLENGE_AUTH, "request": body, "hmac": sign(PASSWORD, &nonce, body) });
+        assert!(Challenge::is_signed_request(&signed_request));
+        assert!(challenge.open("other", &signed_request).is_none());
+        challenge.issue();
+        assert!(challenge.open(PASSWORD, &signed_request).is_none());
+        assert!(verify(PASSWORD, &nonce, body, &sign(PASSWORD, &nonce, body)));
+    }
+
+    #[test]
+    fn fresh_requests_are_accepted_once() {
+        let mut replay_guard = ReplayGuard::default();
+        assert_eq!(replay_guard.verify_at(PASSWORD, &signed_request(NOW, "n1"), NOW), Ok(()));
+        assert_eq!(replay_guard.verify_at(PASSWORD, &signed_request(NOW, "n1"), NOW + 10), Err(SignatureError::Reused));
+        // The clocks are never in sync, a little in the future is fine
+        assert_eq!(replay_guard.verify_at(PASSWORD, &signed_request(NOW + 300, "n2"), NOW), Ok(()));
+        assert_eq!(replay_guard.verify_at(PASSWORD, &signed_request(NOW - 300, "n3"), NOW), Ok(()));
+    }
+
+    #[test]
+    fn stale_requests() {
+        let mut replay_guard = ReplayGuard::default();
+        assert_eq!(replay_guard.verify_at(PASSWORD, &signed_request(NOW - 301, "n1"), NOW), Err(SignatureError::Stale));
+        assert_eq!(replay_guard.verify_at(PASSWORD, &signed_request(NOW + 301, "n2"), NOW), Err(SignatureError::Stale));
+        let mut replay_guard = ReplayGuard::new(time::Duration::from_secs(10));
+        assert_eq!(replay_guard.verify_at(PASSWORD, &signed_request(NOW, "n3"), NOW + 11), Err(SignatureError::Stale));
+    }
+
+    #[test]
+    fn invalid_signatures() {
+        let mut replay_guard = ReplayGuard::default();
+        let mut tampered = signed_request(NOW, "n1");
+        tampered["capacity"] = serde_json::Value::from(5000);
+        assert_eq!(replay_guard.verify_at(PASSWORD, &tampered, NOW), Err(SignatureError::Invalid));
+        let mut tampered = signed_request(NOW, "n1");
+        tampered[TIMESTAMP_FIELD] = serde_json::Value::from(NOW + 1);
+        assert_eq!(replay_guard.verify_at(PASSWORD, &tampered, NOW), Err(SignatureError::Invalid));
+        assert_eq!(replay_guard.verify_at("other", &signed_request(NOW, "n1"), NOW), Err(SignatureError::Invalid));
+        // Rejected requests do not use up the nonce
+        assert_eq!(replay_guard.verify_at(PASSWORD, &signed_request(NOW, "n1"), NOW), Ok(()));
+        let mut unsigned = signed_request(NOW, "n2");
+        unsigned.as_object_mut().unwrap().remove(SIGNATURE_FIELD);
+        assert!(!ReplayGuard::is_signed(&unsigned));
+        assert_eq!(replay_guard.verify_at(PASSWORD, &unsigned, NOW), Err(SignatureError::Missing));
+    }
+
+    // The password sent along the signature is not part of what is signed
+    #[test]
+    fn the_password_is_not_signed() {
+        let mut request = signed_request(NOW, "n1");
+        request["password"] = serde_json::Value::from(PASSWORD);
+        assert_eq!(ReplayGuard::default().verify_at(PASSWORD, &request, NOW), Ok(()));
+    }
+}
@@ -0,0 +1,194 @@
+// Author: Jorge Alarcon Alvarez
+// Email:  jorge4larcon@gmail.com
+// This module is used to save the settings of a server, and optionally its clients, before a
+// maintenance and to set them again later.
+
+extern crate chrono;
+extern crate clap;
+
+use crate::apply;
+use crate::clients;
+use crate::seed;
+use crate::server_config;
+use crate::targets;
+use std::convert::TryFrom;
+use std::fs;
+use std::io::Write;
+use std::path;
+
+// A backup is a JSON file with the non-secret settings, the secrets are left out so the file can
+// be kept anywhere. The clients are stored like in a seed snapshot, so mint-sim --snapshot loads
+// them:
+// {"server":"prod-1","address":"10.0.0.7:7000","created":"2024-05-01T10:00:00+00:00",
+//  "settings":{"capacity":100,"list_size":20,"drop_votes":3,"drop_verification":true},
+//  "clients":[...]}
+pub struct Backup {
+    pub server: String,
+    pub address: String,
+    pub created: String,
+    pub settings: server_config::DesiredConfig,
+    pub clients: Option<clients::ClientsMap>
+}
+
+impl Backup {
+    pub fn to_json_value(&self) -> serde_json::Value {
+        let settings = server_config::Setting::ALL.iter()
+            .filter_map(|setting| Some((setting.field().to_string(), self.settings.get(*setting)?)))
+            .collect::<serde_json::Map<String, serde_json::Value>>();
+        let mut backup = serde_json::json!({
+            "server": self.server,
+            "address": self.address,
+            "created": self.created,
+            "settings": settings
+        });
+        if let (Some(fields), Some(clients)) = (backup.as_object_mut(), &self.clients) {
+            fields.insert(String::from("clients"), seed::to_snapshot(clients)["clients"].take());
+        }
+        backup
+    }
+
+    pub fn from_json_value(backup: &serde_json::Value) -> Result<Backup, String> {
+        let text = |field: &str| backup.get(field).and_then(|value| value.as_str()).unwrap_or_default().to_string();
+        let settings = backup.get("settings").and_then(|settings| settings.as_object()).ok_or("The backup has no settings")?;
+        let desired = server_config::DesiredConfig {
+            capacity: number_setting(settings, "capacity")?,
+            list_size: number_setting(settings, "list_size")?,
+            drop_votes: number_setting(settings, "drop_votes")?,
+            drop_verification: settings.get("drop_verification").and_then(|value| value.as_bool())
+        };
+        desired.validate()?;
+        let clients = match backup.get("clients") {
+            Some(_) => Some(seed::from_snapshot(backup)?),
+            None => None
+        };
+        Ok(Backup { server: text("server"), address: text("address"), created: text("created"), settings: desired, clients })
+    }
+
+    // An existing file is never overwritten
+    pub fn write(&self, path: &path::Path) -> Result<(), String> {
+        let contents = serde_json::to_string_pretty(&self.to_json_value()).map_err(|e| e.to_string())?;
+        fs::OpenOptions::new().write(true).create_new(true).open(path)
+            .and_then(|mut file| file.write_all((contents + "\n").as_bytes()))
+            .map_err(|e| format!("Could not write {}: {}", path.display(), e))
+    }
+
+    pub fn load(path: &path::Path) -> Result<Backup, String> {
+        let contents = fs::read_to_string(path).map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+        let backup = serde_json::from_str::<serde_json::Value>(&contents).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Backup::from_json_value(&backup).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    // e.g. admint-backup-prod-1-20240501T100000123.json, with the milliseconds. The current time
+    // is used when created is not a date.
+    pub fn file_name(&self) -> String {
+        let server: String = self.server.chars().map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '-' }).collect();
+        let created = match chrono::DateTime::parse_from_rfc3339(&self.created) {
+            Ok(created) => created.format("%Y%m%dT%H%M%S%3f").to_string(),
+            Err(_) => chrono::Local::now().format("%Y%m%dT%H%M%S%3f").to_string()
+        };
+        format!("admint-backup-{}-{}.json", server, created)
+    }
+}
+
+fn number_setting<T: TryFrom<u64>>(settings: &serde_json::Map<String, serde_json::Value>, field: &str) -> Result<Option<T>, String> {
+    match settings.get(field) {
+        Some(value) => value.as_u64().and_then(|value| T::try_from(value).ok()).map(Some)
+            .ok_or(format!("{} is not a valid value for {}", value, field)),
+        None => Ok(None)
+    }
+}
+
+pub fn backup(matches: &clap::ArgMatches) -> i32 {
+    let subcommand_matches = match matches.subcommand_matches("backup") {
+        Some(subcommand_matches) => subcommand_matches,
+        None => return 1
+    };
+    let targets = match targets::from_clap_matches(matches) {
+        Ok(targets) => targets,
+        Err(e) => {
+            eprintln!("{}", e);
+            return 1;
+        }
+    };
+    let directory = path::Path::new(subcommand_matches.value_of("output-dir").unwrap_or("."));
+    let mut failed = false;
+    for target in &targets {
+        match backup_target(target, subcommand_matches.is_present("clients"), directory) {
+            Ok(path) => println!("{}: saved to {}", target.name, path.display()),
+            Err(e) => {
+                eprintln!("{}: {}", target.name, e);
+                failed = true;
+            }
+        }
+    }
+    if failed { 1 } else { 0 }
+}
+
+fn backup_target(target: &targets::Target, with_clients: bool, directory: &path::Path) -> Result<path::PathBuf, String> {
+    let config = target.running_config()?;
+    let clients = if with_clients { Some(target.clients()?) } else { None };
+    let backup = Backup {
+        server: target.name.clone(),
+        address: target.address.to_string(),
+        created: chrono::Local::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, false),
+        settings: server_config::DesiredConfig::from_server_config(&config),
+        clients
+    };
+    let path = directory.join(backup.file_name());
+    backup.write(&path)?;
+    Ok(path)
+}
+
+// Sets the settings of the backup that differ, after showing them
+pub fn restore(matches: &clap::ArgMatches) -> i32 {
+    let subcommand_matches = match matches.subcommand_matches("restore") {
+        Some(subcommand_matches) => subcommand_matches,
+        None => return 1
+    };
+    let backup = match subcommand_matches.value_of("file").map(|file| Backup::load(path::Path::new(file))) {
+        Some(Ok(backup)) => backup,
+        Some(Err(e)) => {
+            eprintln!("{}", e);
+            return 1;
+        },
+        None => return 1
+    };
+    let targets = match targets::from_clap_matches(matches) {
+        Ok(targets) => targets,
+        Err(e) => {
+            eprintln!("{}", e);
+            return 1;
+        }
+    };
+    println!("Restoring the backup of {} taken {}", backup.server, backup.created);
+    let options = apply::ApplyOptions::from_clap_matches(subcommand_matches);
+    let mut failed = false;
+    for target in &targets {
+        failed |= !apply::apply_to(target, &backup.settings, &options);
+    }
+    if failed { 1 } else { 0 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn backup(server: &str, created: &str) -> Backup {
+        Backup {
+            server: server.to_string(),
+            address: String::from("10.0.0.7:7000"),
+            created: created.to_string(),
+            settings: server_config::DesiredConfig::default(),
+            clients: None
+        }
+    }
+
+    #[test]
+    fn file_names() {
+        assert_eq!(backup("prod-1", "2024-05-01T10:00:00.123+00:00").file_name(), "admint-backup-prod-1-20240501T100000123.json");
+        assert_eq!(backup("10.0.0.7:7000", "2024-05-01T10:00:00+02:00").file_name(), "admint-backup-10-0-0-7-7000-20240501T100000000.json");
+        let name = backup("prod-1", "yesterday").file_name();
+        let created = name.strip_prefix("admint-backup-prod-1-").and_then(|name| name.strip_suffix(".json")).unwrap();
+        assert!(chrono::NaiveDateTime::parse_from_str(created, "%Y%m%dT%H%M%S%3f").is_ok(), "{}", name);
+    }
+}
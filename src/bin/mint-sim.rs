@@ -0,0 +1,235 @@
+// Author: Jorge Alarcon Alvarez
+// Email:  jorge4larcon@gmail.com
+// This module is where the MINT server simulator parses its options and starts listening.
+
+extern crate clap;
+
+use admint::cli;
+use admint::clients;
+use admint::config;
+use admint::seed;
+use admint::server_config;
+use admint::simulator;
+use admint::tls;
+use clap::App;
+use clap::Arg;
+use std::io::Write;
+use std::path;
+use std::process;
+
+fn ratio_validator(r: String) -> Result<(), String> {
+    match r.parse::<f64>() {
+        Ok(v) if (0.0..=1.0).contains(&v) => Ok(()),
+        _ => Err("This value must be between [0,1]".to_string())
+    }
+}
+
+fn main() {
+    let matches = App::new("MINT-SIM")
+                          .version("1.0")
+                          .author("Jorge A. <jorge4larcon@gmail.com>")
+                          .about("A MINT server simulator that answers the admin requests of admint")
+                          .arg(Arg::with_name("listen")
+                               .long("listen")
+                               .short("l")
+                               .value_name("IP:PORT")
+                               .help("Where to listen, IP:PORT or unix:PATH, use port 0 to get any free port")
+                               .takes_value(true)
+                               .default_value("127.0.0.1:42000"))
+                          .arg(Arg::with_name("capacity")
+                               .long("capacity")
+                               .value_name("CAPACITY")
+                               .takes_value(true)
+                               .default_value("100")
+                               .validator(cli::capacity_validator))
+                          .arg(Arg::with_name("list-size")
+                               .long("list-size")
+                               .value_name("LIST SIZE")
+                               .takes_value(true)
+                               .default_value("20")
+                               .validator(cli::list_size_validator))
+                          .arg(Arg::with_name("drop-votes")
+                               .long("drop-votes")
+                               .value_name("DROP VOTES")
+                               .takes_value(true)
+                               .default_value("3")
+                               .validator(cli::drop_votes_validator))
+                          .arg(Arg::with_name("drop-verification")
+                               .long("drop-verification")
+                               .value_name("DROP VERIFICATION")
+                               .takes_value(true)
+                               .default_value("true")
+                               .validator(cli::bool_validator))
+                          .arg(Arg::with_name("password")
+                               .long("password")
+                               .value_name("PASSWORD")
+                               .help("The password of the clients")
+                               .takes_value(true)
+                               .default_value("")
+                               .validator(cli::key_password_validator))
+                          .arg(Arg::with_name("key")
+                               .long("key")
+                               .value_name("KEY")
+                               .help("The password of the admin")
+                               .takes_value(true)
+                               .default_value("admin")
+                               .validator(cli::key_password_validator))
+                          .arg(Arg::with_name("tls-cert")
+                               .long("tls-cert")
+                               .value_name("FILE")
+                               .help("Serve with TLS, a PEM file with the certificate chain of the server")
+                               .takes_value(true)
+                               .requires("tls-key"))
+                          .arg(Arg::with_name("tls-key")
+                               .long("tls-key")
+                               .value_name("FILE")
+                               .help("A PEM file with the private key of the --tls-cert certificate")
+                               .takes_value(true)
+                               .requires("tls-cert"))
+                          .arg(Arg::with_name("require-signatures")
+                               .long("require-signatures")
+                               .help("Refuse the requests that are not signed (admint --sign)"))
+                          .arg(Arg::with_name("scenario")
+                               .long("scenario")
+                               .value_name("FILE")
+                               .help("A TOML file with the faults to inject in the replies of every command")
+                               .takes_value(true))
+                          .arg(Arg::with_name("snapshot")
+                               .long("snapshot")
+                               .value_name("FILE")
+                               .help("Start with the clients of a snapshot file")
+                               .takes_value(true)
+                               .conflicts_with("generate"))
+                          .arg(Arg::with_name("generate")
+                               .long("generate")
+                               .value_name("COUNT")
+                               .help("Start with COUNT synthetic clients, the same seed gives the same clients")
+                               .takes_value(true)
+                               .validator(cli::usize_validator))
+                          .arg(Arg::with_name("seed")
+                               .long("seed")
+                               .value_name("SEED")
+                               .takes_value(true)
+                               .default_value("0")
+                               .validator(cli::u64_validator))
+                          .arg(Arg::with_name("subnet")
+                               .long("subnet")
+                               .value_name("IPv4/PREFIX")
+                               .help("A subnet for the addresses of the synthetic clients, 192.168.0.0/16 by default")
+                               .takes_value(true)
+                               .multiple(true)
+                               .number_of_values(1)
+                               .validator(seed::is_subnet))
+                          .arg(Arg::with_name("mac-only-ratio")
+                               .long("mac-only-ratio")
+                               .value_name("RATIO")
+                               .help("The share of synthetic clients that can only be found by their MAC address")
+                               .takes_value(true)
+                               .default_value("0.2")
+                               .validator(ratio_validator))
+                          .arg(Arg::with_name("write-snapshot")
+                               .long("write-snapshot")
+                               .value_name("FILE")
+                               .help("Write the clients to a snapshot file and exit instead of listening")
+                               .takes_value(true))
+                          .arg(Arg::with_name("verbose")
+                               .short("v")
+                               .long("verbose")
+                               .multiple(true)
+                               .help("Log more, -v info, -vv debug, -vvv trace"))
+                          .get_matches();
+
+    // The validators already checked every value
+    let default_config = server_config::ServerConfig::default();
+    let server_config = server_config::ServerConfig::new(
+        matches.value_of("capacity").and_then(|c| c.parse().ok()).unwrap_or(default_config.capacity()),
+        matches.value_of("list-size").and_then(|ls| ls.parse().ok()).unwrap_or(default_config.list_size()),
+        matches.value_of("drop-votes").and_then(|dv| dv.parse().ok()).unwrap_or(default_config.drop_votes()),
+        matches.value_of("drop-verification").and_then(|dv| dv.parse().ok()).unwrap_or(default_config.drop_verification()),
+        matches.value_of("password").unwrap_or(default_config.password()),
+        matches.value_of("key").unwrap_or(default_config.key())
+    ).unwrap_or(default_config);
+
+    let log_config = config::LogConfig {
+        level: config::level_from_verbosity(matches.occurrences_of("verbose"), 0),
+        ..config::LogConfig::default()
+    };
+    if config::setup_logging(&log_config).is_err() {
+        eprintln!("Could not set up logging");
+        process::exit(1);
+    }
+
+    let scenario = match matches.value_of("scenario") {
+        Some(scenario) => match simulator::Scenario::load(path::Path::new(scenario)) {
+            Ok(scenario) => scenario,
+            Err(e) => {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        },
+        None => simulator::Scenario::default()
+    };
+
+    let clients_map = match (matches.value_of("snapshot"), matches.value_of("generate")) {
+        (Some(snapshot), _) => seed::load_snapshot(path::Path::new(snapshot)),
+        (None, Some(count)) => {
+            let default_options = seed::SeedOptions::default();
+            let seed_options = seed::SeedOptions {
+                count: count.parse().unwrap_or(default_options.count),
+                seed: matches.value_of("seed").and_then(|s| s.parse().ok()).unwrap_or(default_options.seed),
+                subnets: match matches.values_of("subnet") {
+                    Some(subnets) => subnets.filter_map(seed::Subnet::new_from_str).collect(),
+                    None => default_options.subnets.clone()
+                },
+                get_only_by_mac_ratio: matches.value_of("mac-only-ratio").and_then(|r| r.parse().ok()).unwrap_or(default_options.get_only_by_mac_ratio),
+                ..default_options
+            };
+            seed::generate(&seed_options)
+        },
+        (None, None) => Ok(clients::ClientsMap::new())
+    };
+    let clients_map = match clients_map {
+        Ok(clients_map) => clients_map,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    };
+    if let Some(snapshot) = matches.value_of("write-snapshot") {
+        match seed::write_snapshot(path::Path::new(snapshot), &clients_map) {
+            Ok(()) => process::exit(0),
+            Err(e) => {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        }
+    }
+
+    let listen = matches.value_of("listen").unwrap_or("127.0.0.1:42000");
+    let mut state = simulator::SimState::new(clients_map, server_config).with_scenario(scenario);
+    if matches.is_present("require-signatures") {
+        state = state.with_required_signatures();
+    }
+    let mut simulator = match simulator::Simulator::bind(listen, state) {
+        Ok(simulator) => simulator,
+        Err(e) => {
+            eprintln!("Could not listen on {}: {}", listen, e);
+            process::exit(1);
+        }
+    };
+    if let (Some(cert_file), Some(key_file)) = (matches.value_of("tls-cert"), matches.value_of("tls-key")) {
+        match tls::server_config(path::Path::new(cert_file), path::Path::new(key_file)) {
+            Ok(tls_config) => simulator = simulator.with_tls(tls_config),
+            Err(e) => {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        }
+    }
+    // Scripts read this line to learn the port when it was 0
+    if let Ok(address) = simulator.address() {
+        println!("mint-sim listening on {}", address);
+        let _ = std::io::stdout().flush();
+    }
+    simulator.serve();
+}
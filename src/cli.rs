@@ -0,0 +1,811 @@
+// Author: Jorge Alarcon Alvarez
+// Email:  jorge4larcon@gmail.com
+// This module is where the command line of the program is defined, so the binary and the tests
+// parse the commands the same way.
+
+extern crate clap;
+extern crate regex;
+
+use crate::ipparser;
+use crate::tls;
+use crate::proxy;
+use crate::secret;
+use crate::server_config;
+use clap::AppSettings;
+use clap::SubCommand;
+use clap::App;
+use clap::Arg;
+use regex::Regex;
+
+fn ipv4_address_validator(addr: String) -> Result<(), String> {
+    if ipparser::is_ipv4_addr(&addr) {
+        return Ok(());
+    }
+    Err(format!("{} is not a valid IPv4 address ", addr))
+}
+
+fn proxy_validator(url: String) -> Result<(), String> {
+    if proxy::is_proxy(&url) {
+        return Ok(());
+    }
+    Err(format!("{} is not a valid proxy, use socks5://[USER:PASSWORD@]HOST:PORT or http://[USER:PASSWORD@]HOST:PORT", url))
+}
+
+fn server_address_validator(addr: String) -> Result<(), String> {
+    if ipparser::is_server_address(&addr) {
+        return Ok(());
+    }
+    Err(format!("{} is not a valid server address, use IP:PORT, HOSTNAME:PORT or unix:PATH", addr))
+}
+
+// Profile names can not be checked until the profiles are loaded, only the empty ones are refused
+fn profile_or_address_validator(server: String) -> Result<(), String> {
+    if !server.trim().is_empty() {
+        return Ok(());
+    }
+    Err(String::from("The server must be a profile name or a server address"))
+}
+
+pub fn bool_validator(b: String) -> Result<(), String> {
+    if let Ok(_b) = b.parse::<bool>() {
+        return Ok(());
+    }
+    Err(format!("{} is not a valid boolean value", b))
+}
+
+pub fn list_size_validator(ll: String) -> Result<(), String> {
+    if let Ok(_ll) = ll.parse::<u16>() {
+        return Ok(());
+    }
+    Err(format!("{} is not a valid list size number, this value must be between [0,65535]", ll))
+}
+
+pub fn usize_validator(num: String) -> Result<(), String> {
+    if let Ok(_n) = num.parse::<usize>() {
+        return Ok(());
+    }
+    Err(format!("{} is not a valid unsigned number", num))
+}
+
+pub fn u64_validator(num: String) -> Result<(), String> {
+    if let Ok(_n) = num.parse::<u64>() {
+        return Ok(());
+    }
+    Err(format!("{} is not a valid unsigned number", num))
+}
+
+fn u32_validator(num: String) -> Result<(), String> {
+    if let Ok(_n) = num.parse::<u32>() {
+        return Ok(());
+    }
+    Err(format!("{} is not a valid unsigned number", num))
+}
+
+fn seconds_validator(secs: String) -> Result<(), String> {
+    if let Ok(s) = secs.parse::<f64>() {
+        if s.is_finite() && s > 0.0 {
+            return Ok(());
+        }
+    }
+    Err(format!("{} is not a valid number of seconds, it must be greater than 0", secs))
+}
+
+fn fingerprint_validator(fingerprint: String) -> Result<(), String> {
+    if tls::is_fingerprint(&fingerprint) {
+        return Ok(());
+    }
+    Err(format!("{} is not a valid SHA-256 fingerprint, it must have 64 hex digits", fingerprint))
+}
+
+fn mac_validator(mac: String) -> Result<(), String> {
+    if mac.is_ascii() {
+        let mac_re = Regex::new(r"^((([a-fA-F0-9][a-fA-F0-9]+[-]){5}|([a-fA-F0-9][a-fA-F0-9]+[:]){5})([a-fA-F0-9][a-fA-F0-9])$)|(^([a-fA-F0-9][a-fA-F0-9][a-fA-F0-9][a-fA-F0-9]+[.]){2}([a-fA-F0-9][a-fA-F0-9][a-fA-F0-9][a-fA-F0-9]))$").unwrap();
+        if mac_re.is_match(&mac) {
+            return Ok(());
+        }
+    }
+    Err(format!("{} is not a valid mac address", mac))
+}
+
+fn username_pattern_validator(pattern: String) -> Result<(), String> {
+    if pattern.is_ascii() {
+        return Ok(());
+    }
+    Err(format!("{} is not a valid pattern", pattern))
+}
+
+pub fn key_password_validator(key: String) -> Result<(), String> {
+    if server_config::is_valid_secret(&key) {
+        return Ok(());
+    }
+    Err(server_config::SECRET_ERROR.to_string())
+}
+
+fn secret_length_validator(length: String) -> Result<(), String> {
+    match length.parse::<usize>() {
+        Ok(length) if secret::is_valid_length(length) => Ok(()),
+        _ => Err(format!("The length must be between [1,{}]", secret::MAX_LENGTH))
+    }
+}
+
+fn alphabet_validator(alphabet: String) -> Result<(), String> {
+    if secret::is_valid_alphabet(&alphabet) {
+        return Ok(());
+    }
+    Err(String::from("The alphabet must be alphanumeric, hex, symbols or at least 2 different printable ASCII characters"))
+}
+
+pub fn capacity_validator(c: String) -> Result<(), String> {
+    match c.parse::<u16>() {
+        Ok(v) if server_config::is_valid_capacity(v) => Ok(()),
+        _ => Err(server_config::CAPACITY_ERROR.to_string())
+    }
+}
+
+pub fn drop_votes_validator(c: String) -> Result<(), String> {
+    match c.parse::<u8>() {
+        Ok(v) if server_config::is_valid_drop_votes(v) => Ok(()),
+        _ => Err(server_config::DROP_VOTES_ERROR.to_string())
+    }
+}
+
+// The servers of the subcommands that act on many of them, they start at the positional index
+fn with_targets(subcommand: App<'static, 'static>, index: u64, group_help: &'static str) -> App<'static, 'static> {
+    subcommand.arg(Arg::with_name("server")
+                   .index(index)
+                   .value_name("SERVER")
+                   .help("A profile name or a server address")
+                   .takes_value(true)
+                   .multiple(true)
+                   .required_unless("group")
+                   .validator(profile_or_address_validator))
+              .arg(Arg::with_name("group")
+                   .long("group")
+                   .short("g")
+                   .value_name("GROUP")
+                   .help(group_help)
+                   .takes_value(true)
+                   .number_of_values(1))
+              .arg(Arg::with_name("admin-password")
+                   .short("P")
+                   .long("admin-password")
+                   .value_name("ADMIN PASSWORD")
+                   .help("The admin password of the servers given by address")
+                   .takes_value(true)
+                   .number_of_values(1)
+                   .validator(key_password_validator))
+}
+
+// The value is generated when --generate is given instead of it. The positional admin password and
+// server address can not be given without the value, so they come from the --profile.
+fn with_generate(subcommand: App<'static, 'static>, value: &'static str) -> App<'static, 'static> {
+    subcommand.arg(Arg::with_name("generate")
+                   .long("generate")
+                   .short("g")
+                   .help("Generate a random value, set it and show it")
+                   .conflicts_with(value))
+              .arg(Arg::with_name("length")
+                   .long("length")
+                   .value_name("LENGTH")
+                   .help("The length of the generated value, 16 for passwords and 32 for keys by default")
+                   .takes_value(true)
+                   .number_of_values(1)
+                   .requires("generate")
+                   .validator(secret_length_validator))
+              .arg(Arg::with_name("alphabet")
+                   .long("alphabet")
+                   .value_name("ALPHABET")
+                   .help("alphanumeric (the default), hex, symbols or the characters to generate the value from")
+                   .takes_value(true)
+                   .number_of_values(1)
+                   .requires("generate")
+                   .validator(alphabet_validator))
+}
+
+pub fn build_app() -> App<'static, 'static> {
+    App::new("ADMINT")
+                          .version("1.0")
+                          .author("Jorge A. <jorge4larcon@gmail.com>")
+                          .about("ADministration tool for MINT server")
+                          .setting(AppSettings::ArgRequiredElseHelp)
+                          .arg(Arg::with_name("verbose")
+                               .short("v")
+                               .long("verbose")
+                               .help("Show more log messages (-v info, -vv debug, -vvv trace)")
+                               .multiple(true)
+                               .global(true))
+                          .arg(Arg::with_name("quiet")
+                               .short("q")
+                               .long("quiet")
+                               .help("Show less log messages (-q only errors, -qq nothing)")
+                               .multiple(true)
+                               .global(true)
+                               .conflicts_with("verbose"))
+                          .arg(Arg::with_name("log-file")
+                               .long("log-file")
+                               .value_name("FILE")
+                               .help("Also write the log messages to this file, it is rotated when it gets too big")
+                               .takes_value(true)
+                               .number_of_values(1)
+                               .global(true))
+                          .arg(Arg::with_name("log-max-size")
+                               .long("log-max-size")
+                               .value_name("BYTES")
+                               .help("The size in bytes at which the log file is rotated")
+                               .takes_value(true)
+                               .number_of_values(1)
+                               .global(true)
+                               .validator(u64_validator))
+                          .arg(Arg::with_name("log-max-files")
+                               .long("log-max-files")
+                               .value_name("COUNT")
+                               .help("How many rotated log files are kept, 5 by default")
+                               .takes_value(true)
+                               .number_of_values(1)
+                               .global(true)
+                               .validator(usize_validator))
+                          .arg(Arg::with_name("log-format")
+                               .long("log-format")
+                               .value_name("FORMAT")
+                               .help("The format of the log messages")
+                               .takes_value(true)
+                               .number_of_values(1)
+                               .possible_values(&["text", "json"])
+                               .global(true))
+                          .arg(Arg::with_name("config")
+                               .long("config")
+                               .value_name("FILE")
+                               .help("The profiles file, by default $ADMINT_CONFIG or ~/.config/admint/profiles.toml")
+                               .takes_value(true)
+                               .number_of_values(1)
+                               .global(true))
+                          .arg(Arg::with_name("profile")
+                               .long("profile")
+                               .value_name("PROFILE")
+                               .help("Read the server address, admin password and connection settings from this profile")
+                               .takes_value(true)
+                               .number_of_values(1)
+                               .global(true))
+                          .arg(Arg::with_name("connect-timeout")
+                               .long("connect-timeout")
+                               .value_name("SECONDS")
+                               .help("How long to wait for the connection with the server, by default the OS decides")
+                               .takes_value(true)
+                               .number_of_values(1)
+                               .global(true)
+                               .validator(seconds_validator))
+                          .arg(Arg::with_name("timeout")
+                               .long("timeout")
+                               .value_name("SECONDS")
+                               .help("How long to wait for every read and write, 10 seconds by default")
+                               .takes_value(true)
+                               .number_of_values(1)
+                               .global(true)
+                               .validator(seconds_validator))
+                          .arg(Arg::with_name("retries")
+                               .long("retries")
+                               .value_name("RETRIES")
+                               .help("How many times a get command is retried when the server does not answer, drop and set commands are never retried")
+                               .takes_value(true)
+                               .number_of_values(1)
+                               .global(true)
+                               .validator(u32_validator))
+                          .arg(Arg::with_name("tls")
+                               .long("tls")
+                               .help("Protect the connection with TLS, it is enabled too by any other --tls-* option")
+                               .global(true))
+                          .arg(Arg::with_name("tls-ca-file")
+                               .long("tls-ca-file")
+                               .value_name("FILE")
+                               .help("PEM file with the CAs trusted to sign the server certificate, the Mozilla roots by default")
+                               .takes_value(true)
+                               .number_of_values(1)
+                               .global(true))
+                          .arg(Arg::with_name("tls-client-cert")
+                               .long("tls-client-cert")
+                               .value_name("FILE")
+                               .help("PEM file with the certificate chain presented to the server")
+                               .takes_value(true)
+                               .number_of_values(1)
+                               .global(true)
+                               .requires("tls-client-key"))
+                          .arg(Arg::with_name("tls-client-key")
+                               .long("tls-client-key")
+                               .value_name("FILE")
+                               .help("PEM file with the private key of the client certificate")
+                               .takes_value(true)
+                               .number_of_values(1)
+                               .global(true)
+                               .requires("tls-client-cert"))
+                          .arg(Arg::with_name("tls-fingerprint")
+                               .long("tls-fingerprint")
+                               .value_name("SHA256")
+                               .help("Trust only the server certificate with this SHA-256 fingerprint, whoever signed it")
+                               .takes_value(true)
+                               .number_of_values(1)
+                               .global(true)
+                               .validator(fingerprint_validator))
+                          .arg(Arg::with_name("tls-server-name")
+                               .long("tls-server-name")
+                               .value_name("NAME")
+                               .help("The name checked against the server certificate, the host of the server address by default")
+                               .takes_value(true)
+                               .number_of_values(1)
+                               .global(true))
+                          .arg(Arg::with_name("proxy")
+                               .long("proxy")
+                               .value_name("URL")
+                               .help("Reach the server through this SOCKS5 or HTTP CONNECT proxy, socks5://[USER:PASSWORD@]HOST:PORT or http://[USER:PASSWORD@]HOST:PORT, ALL_PROXY by default except for the hosts in NO_PROXY")
+                               .takes_value(true)
+                               .number_of_values(1)
+                               .global(true)
+                               .validator(proxy_validator))
+                          .arg(Arg::with_name("auth")
+                               .long("auth")
+                               .value_name("MODE")
+                               .help("How to prove the admin password: send it (password, the default) or answer a challenge of the server with an HMAC of it, so it never crosses the wire (challenge)")
+                               .takes_value(true)
+                               .number_of_values(1)
+                               .possible_values(&["password", "challenge"])
+                               .global(true))
+                          .arg(Arg::with_name("sign")
+                               .long("sign")
+                               .help("Sign every request with the admin password, with a timestamp and a nonce so it can not be replayed")
+                               .global(true))
+                          .subcommand(with_targets(SubCommand::with_name("config-diff")
+                                       .about("Compare the settings of many servers, the exit code is 1 when they differ and 2 when they could not be compared")
+                                       .version("1.0")
+                                       .author("Jorge A. <jorge4larcon@gmail.com>")
+                                       .arg(Arg::with_name("baseline")
+                                            .long("baseline")
+                                            .short("b")
+                                            .value_name("FILE")
+                                            .help("Compare every server with the settings of this TOML file")
+                                            .takes_value(true)
+                                            .number_of_values(1)), 1, "Compare every profile of this group"))
+                          .subcommand(with_targets(SubCommand::with_name("apply")
+                                       .about("Set only the settings of the servers that differ from the ones of a TOML file")
+                                       .version("1.0")
+                                       .author("Jorge A. <jorge4larcon@gmail.com>")
+                                       .arg(Arg::with_name("file")
+                                            .index(1)
+                                            .value_name("FILE")
+                                            .help("The desired settings, e.g. capacity = 100")
+                                            .takes_value(true)
+                                            .required(true))
+                                       .arg(Arg::with_name("yes")
+                                            .long("yes")
+                                            .short("y")
+                                            .help("Do not ask before changing a server"))
+                                       .arg(Arg::with_name("dry-run")
+                                            .long("dry-run")
+                                            .help("Only show what would change"))
+                                       .arg(Arg::with_name("transactional")
+                                            .long("transactional")
+                                            .short("t")
+                                            .help("When a setting can not be set, set the ones already changed back to their previous values")), 2, "Apply to every profile of this group"))
+                          .subcommand(with_targets(SubCommand::with_name("backup")
+                                       .about("Save the settings of the servers, and optionally their clients, to timestamped files")
+                                       .version("1.0")
+                                       .author("Jorge A. <jorge4larcon@gmail.com>")
+                                       .arg(Arg::with_name("clients")
+                                            .long("clients")
+                                            .short("c")
+                                            .help("Save the clients too"))
+                                       .arg(Arg::with_name("output-dir")
+                                            .long("output-dir")
+                                            .short("o")
+                                            .value_name("DIRECTORY")
+                                            .help("Where to save the backups, the current directory by default")
+                                            .takes_value(true)
+                                            .number_of_values(1)), 1, "Back up every profile of this group"))
+                          .subcommand(with_targets(SubCommand::with_name("restore")
+                                       .about("Set again the settings of a backup that differ, the secrets are not restored")
+                                       .version("1.0")
+                                       .author("Jorge A. <jorge4larcon@gmail.com>")
+                                       .arg(Arg::with_name("file")
+                                            .index(1)
+                                            .value_name("FILE")
+                                            .help("A file written by backup")
+                                            .takes_value(true)
+                                            .required(true))
+                                       .arg(Arg::with_name("yes")
+                                            .long("yes")
+                                            .short("y")
+                                            .help("Do not ask before changing a server"))
+                                       .arg(Arg::with_name("dry-run")
+                                            .long("dry-run")
+                                            .help("Only show what would change"))
+                                       .arg(Arg::with_name("transactional")
+                                            .long("transactional")
+                                            .short("t")
+                                            .help("When a setting can not be set, set the ones already changed back to their previous values")), 2, "Restore to every profile of this group"))
+                          .subcommand(with_targets(SubCommand::with_name("rotate-key")
+                                       .about("Set a generated admin password in the servers, verify it and store it in their profiles")
+                                       .version("1.0")
+                                       .author("Jorge A. <jorge4larcon@gmail.com>"), 1, "Rotate the key of every profile of this group"))
+                          .subcommand(SubCommand::with_name("running-config")
+                                       .about("Get the running config of the server")
+                                       .version("1.0")
+                                       .author("Jorge A. <jorge4larcon@gmail.com>")                                       
+                                       .arg(Arg::with_name("get")
+                                            .long("get")
+                                            .value_name("SETTING")
+                                            .help("Show only the value of this setting")
+                                            .takes_value(true)
+                                            .number_of_values(1)
+                                            .possible_values(&server_config::SETTING_NAMES))
+                                       .arg(Arg::with_name("format")
+                                            .long("format")
+                                            .value_name("FORMAT")
+                                            .help("The format of the running config, text by default")
+                                            .takes_value(true)
+                                            .number_of_values(1)
+                                            .possible_values(&["text", "json", "toml"]))
+                                       .arg(Arg::with_name("admin-password")
+                                            .index(1)
+                                            .short("P")
+                                            .long("admin-password")
+                                            .value_name("ADMIN PASSWORD")
+                                            .help("The admin password of the server")
+                                            .takes_value(true)
+                                            .number_of_values(1)
+                                            .validator(key_password_validator))
+                                       .arg(Arg::with_name("server-address")
+                                            .index(2)
+                                            .short("a")
+                                            .long("server-address")
+                                            .value_name("SERVER ADDRESS")
+                                            .help("The address of the server, IP:PORT, HOSTNAME:PORT or unix:PATH")
+                                            .takes_value(true)
+                                            .number_of_values(1)
+                                            .validator(server_address_validator)))
+                          .subcommand(SubCommand::with_name("set-dropvotes")
+                                       .about("Set the drop votes of the server, this command can drop users that are logged in the server")
+                                       .version("1.0")
+                                       .author("Jorge A. <jorge4larcon@gmail.com>")
+                                       .arg(Arg::with_name("drop-votes")
+                                            .index(1)
+                                            .short("d")
+                                            .long("drop-votes")
+                                            .value_name("DROP VOTES")
+                                            .help("The new drop votes value for the server")
+                                            .takes_value(true)
+                                            .required(true)
+                                            .number_of_values(1)
+                                            .validator(drop_votes_validator))
+                                        .arg(Arg::with_name("admin-password")
+                                            .index(2)
+                                            .short("P")
+                                            .long("admin-password")
+                                            .value_name("ADMIN PASSWORD")
+                                            .help("The admin password of the server")
+                                            .takes_value(true)
+                                            .number_of_values(1)
+                                            .validator(key_password_validator))
+                                        .arg(Arg::with_name("server-address")
+                                            .index(3)
+                                            .short("a")
+                                            .long("server-address")
+                                            .value_name("SERVER ADDRESS")
+                                            .help("The address of the server, IP:PORT, HOSTNAME:PORT or unix:PATH")
+                                            .takes_value(true)
+                                            .number_of_values(1)
+                                            .validator(server_address_validator)))
+                          .subcommand(SubCommand::with_name("set-dropverification")
+                                       .about("Enable/Disable the drop verification in the server")
+                                       .version("1.0")
+                                       .author("Jorge A. <jorge4larcon@gmail.com>")
+                                       .arg(Arg::with_name("state")
+                                            .index(1)
+                                            .short("s")
+                                            .long("state")
+                                            .value_name("STATE")
+                                            .help("The new state of the drop verification in the server")
+                                            .takes_value(true)
+                                            .required(true)
+                                            .number_of_values(1)
+                                            .validator(bool_validator))
+                                       .arg(Arg::with_name("admin-password")
+                                            .index(2)
+                                            .short("P")
+                                            .long("admin-password")
+                                            .value_name("ADMIN PASSWORD")
+                                            .help("The admin password of the server")
+                                            .takes_value(true)
+                                            .number_of_values(1)
+                                            .validator(key_password_validator))
+                                       .arg(Arg::with_name("server-address")
+                                            .index(3)
+                                            .short("a")
+                                            .long("server-address")
+                                            .value_name("SERVER ADDRESS")
+                                            .help("The address of the server, IP:PORT, HOSTNAME:PORT or unix:PATH")
+                                            .takes_value(true)
+                                            .number_of_values(1)
+                                            .validator(server_address_validator)))
+                          .subcommand(SubCommand::with_name("set-listsize")
+                                       .about("Set the list size of the server")
+                                       .version("1.0")
+                                       .author("Jorge A. <jorge4larcon@gmail.com>")
+                                       .arg(Arg::with_name("list-size")
+                                            .index(1)
+                                            .short("l")
+                                            .long("list-size")
+                                            .value_name("LIST SIZE")
+                                            .help("The new list size of the server")
+                                            .takes_value(true)
+                                            .required(true)
+                                            .number_of_values(1)
+                                            .validator(list_size_validator))
+                                       .arg(Arg::with_name("admin-password")
+                                            .index(2)
+                                            .short("P")
+                                            .long("admin-password")
+                                            .value_name("ADMIN PASSWORD")
+                                            .help("The admin password of the server")
+                                            .takes_value(true)
+                                            .number_of_values(1)
+                                            .validator(key_password_validator))
+                                       .arg(Arg::with_name("server-address")
+                                            .index(3)
+                                            .short("a")
+                                            .long("server-address")
+                                            .value_name("SERVER ADDRESS")
+                                            .help("The address of the server, IP:PORT, HOSTNAME:PORT or unix:PATH")
+                                            .takes_value(true)
+                                            .number_of_values(1)
+                                            .validator(server_address_validator))
+                                       .arg(Arg::with_name("force")
+                                            .long("force")
+                                            .short("f")
+                                            .help("Set it even when it conflicts with the other settings or the connected clients")))
+                          .subcommand(SubCommand::with_name("set-capacity")                          
+                                       .about("Set the capacity of the server")
+                                       .version("1.0")
+                                       .author("Jorge A. <jorge4larcon@gmail.com>")
+                                       .arg(Arg::with_name("capacity")
+                                            .index(1)
+                                            .short("c")
+                                            .long("capacity")
+                                            .value_name("CAPACITY")
+                                            .help("The new capacity of the server")
+                                            .takes_value(true)
+                                            .required(true)
+                                            .number_of_values(1)
+                                            .validator(capacity_validator))
+                                       .arg(Arg::with_name("admin-password")
+                                            .index(2)
+                                            .short("P")
+                                            .long("admin-password")
+                                            .value_name("ADMIN PASSWORD")
+                                            .help("The admin password of the server")
+                                            .takes_value(true)
+                                            .number_of_values(1)
+                                            .validator(key_password_validator))
+                                       .arg(Arg::with_name("server-address")
+                                            .index(3)
+                                            .short("a")
+                                            .long("server-address")
+                                            .value_name("SERVER ADDRESS")
+                                            .help("The address of the server, IP:PORT, HOSTNAME:PORT or unix:PATH")
+                                            .takes_value(true)
+                                            .number_of_values(1)
+                                            .validator(server_address_validator))
+                                       .arg(Arg::with_name("force")
+                                            .long("force")
+                                            .short("f")
+                                            .help("Set it even when it conflicts with the other settings or the connected clients")))
+                          .subcommand(with_generate(SubCommand::with_name("set-password")
+                                       .about("Set the password for the normal users")
+                                       .version("1.0")
+                                       .author("Jorge A. <jorge4larcon@gmail.com>")
+                                       .arg(Arg::with_name("password")
+                                            .index(1)
+                                            .short("p")
+                                            .long("password")
+                                            .value_name("PASSWORD")
+                                            .help("The new password for the clients")
+                                            .takes_value(true)
+                                            .required_unless("generate")
+                                            .number_of_values(1)
+                                            .validator(key_password_validator))
+                                       .arg(Arg::with_name("admin-password")
+                                            .index(2)
+                                            .short("P")
+                                            .long("admin-password")
+                                            .value_name("ADMIN PASSWORD")
+                                            .help("The admin password of the server")
+                                            .takes_value(true)
+                                            .number_of_values(1)
+                                            .validator(key_password_validator))
+                                       .arg(Arg::with_name("server-address")
+                                            .index(3)
+                                            .short("a")
+                                            .long("server-address")
+                                            .value_name("SERVER ADDRESS")
+                                            .help("The address of the server, IP:PORT, HOSTNAME:PORT or unix:PATH")
+                                            .takes_value(true)
+                                            .number_of_values(1)
+                                            .validator(server_address_validator)), "password"))
+                          .subcommand(with_generate(SubCommand::with_name("set-key")
+                                       .about("Set the password for the admin user")
+                                       .version("1.0")
+                                       .author("Jorge A. <jorge4larcon@gmail.com>")
+                                       .arg(Arg::with_name("key")
+                                            .index(1)
+                                            .short("k")
+                                            .long("key")
+                                            .value_name("KEY")
+                                            .help("The new password for the admin")
+                                            .takes_value(true)
+                                            .required_unless("generate")
+                                            .number_of_values(1)
+                                            .validator(key_password_validator))
+                                       .arg(Arg::with_name("admin-password")
+                                            .index(2)
+                                            .short("P")
+                                            .long("admin-password")
+                                            .value_name("ADMIN PASSWORD")
+                                            .help("The admin password of the server")
+                                            .takes_value(true)
+                                            .number_of_values(1)
+                                            .validator(key_password_validator))
+                                       .arg(Arg::with_name("server-address")
+                                            .index(3)
+                                            .short("a")
+                                            .long("server-address")
+                                            .value_name("SERVER ADDRESS")
+                                            .help("The address of the server, IP:PORT, HOSTNAME:PORT or unix:PATH")
+                                            .takes_value(true)
+                                            .number_of_values(1)
+                                            .validator(server_address_validator)), "key"))
+                          .subcommand(SubCommand::with_name("drop")
+                                       .about("Drop a client from the server with an specific ip address")
+                                       .version("1.0")
+                                       .author("Jorge A. <jorge4larcon@gmail.com>")
+                                       .arg(Arg::with_name("ip")
+                                            .index(1)
+                                            .short("i")
+                                            .long("ip")
+                                            .value_name("IP ADDRESS")
+                                            .help("The IPv4 address of the client to drop (can be more than one)")
+                                            .takes_value(true)
+                                            .required(true)
+                                            .number_of_values(1)
+                                            .validator(ipv4_address_validator))
+                                       .arg(Arg::with_name("admin-password")
+                                            .index(2)
+                                            .short("P")
+                                            .long("admin-password")
+                                            .value_name("ADMIN PASSWORD")
+                                            .help("The admin password of the server")
+                                            .takes_value(true)
+                                            .number_of_values(1)
+                                            .validator(key_password_validator))
+                                       .arg(Arg::with_name("server-address")
+                                            .index(3)
+                                            .short("a")
+                                            .long("server-address")
+                                            .value_name("SERVER ADDRESS")
+                                            .help("The address of the server, IP:PORT, HOSTNAME:PORT or unix:PATH")
+                                            .takes_value(true)
+                                            .number_of_values(1)
+                                            .validator(server_address_validator)))
+                          .subcommand(SubCommand::with_name("get-mac")
+                                       .about("Get a client from the server with an specific mac address")
+                                       .version("1.0")
+                                       .author("Jorge A. <jorge4larcon@gmail.com>")
+                                       .arg(Arg::with_name("mac")
+                                            .index(1)
+                                            .short("m")
+                                            .long("mac")
+                                            .value_name("MAC")
+                                            .long_help("The mac to search, it could be aaaa.bbbb.cccc, aa:aa:bb:bb:cc:cc or aa-aa-bb-bb-cc-cc")
+                                            .takes_value(true)
+                                            .required(true)
+                                            .number_of_values(1)
+                                            .validator(mac_validator))
+                                       .arg(Arg::with_name("admin-password")
+                                            .index(2)
+                                            .short("P")
+                                            .long("admin-password")
+                                            .value_name("ADMIN PASSWORD")
+                                            .help("The admin password of the server")
+                                            .takes_value(true)
+                                            .number_of_values(1)
+                                            .validator(key_password_validator))
+                                       .arg(Arg::with_name("server-address")
+                                            .index(3)
+                                            .short("a")
+                                            .long("server-address")
+                                            .value_name("SERVER ADDRESS")
+                                            .help("The address of the server, IP:PORT, HOSTNAME:PORT or unix:PATH")
+                                            .takes_value(true)
+                                            .number_of_values(1)
+                                            .validator(server_address_validator)))
+                          .subcommand(SubCommand::with_name("get-username")
+                                       .about("Get a list of clients from the server with an specific pattern in their usernames")
+                                       .version("1.0")
+                                       .author("Jorge A. <jorge4larcon@gmail.com>")
+                                       .arg(Arg::with_name("pattern")
+                                            .index(1)
+                                            .short("p")
+                                            .long("pattern")
+                                            .value_name("PATTERN")
+                                            .help("The pattern to search")
+                                            .takes_value(true)
+                                            .required(true)
+                                            .number_of_values(1)
+                                            .validator(username_pattern_validator))
+                                        .arg(Arg::with_name("start")
+                                            .index(2)
+                                            .short("s")
+                                            .long("start")
+                                            .value_name("START_INDEX")
+                                            .help("The start index of the list")
+                                            .takes_value(true)
+                                            .required(true)
+                                            .number_of_values(1)
+                                            .validator(usize_validator))
+                                       .arg(Arg::with_name("admin-password")
+                                            .index(3)
+                                            .short("P")
+                                            .long("admin-password")
+                                            .value_name("ADMIN PASSWORD")
+                                            .help("The admin password of the server")
+                                            .takes_value(true)
+                                            .number_of_values(1)
+                                            .validator(key_password_validator))
+                                       .arg(Arg::with_name("server-address")
+                                            .index(4)
+                                            .short("a")
+                                            .long("server-address")
+                                            .value_name("SERVER ADDRESS")
+                                            .help("The address of the server, IP:PORT, HOSTNAME:PORT or unix:PATH")
+                                            .takes_value(true)
+                                            .number_of_values(1)
+                                            .validator(server_address_validator)))
+                          .subcommand(SubCommand::with_name("get-index")
+                                       .about("Get a list of clients from the server")
+                                       .version("1.0")
+                                       .author("Jorge A. <jorge4larcon@gmail.com>")
+                                       .arg(Arg::with_name("start")
+                                            .index(1)
+                                            .short("s")
+                                            .long("start")
+                                            .value_name("START_INDEX")
+                                            .help("The start index of the list")                                            
+                                            .takes_value(true)
+                                            .required(true)
+                                            .number_of_values(1)
+                                            .validator(usize_validator))
+                                        .arg(Arg::with_name("end")
+                                            .index(2)
+                                            .short("e")
+                                            .long("end")
+                                            .value_name("END_INDEX")
+                                            .help("The end index of the list")
+                                            .takes_value(true)
+                                            .required(true)
+                                            .number_of_values(1)
+                                            .validator(usize_validator))
+                                       .arg(Arg::with_name("admin-password")
+                                            .index(3)
+                                            .short("P")
+                                            .long("admin-password")
+                                            .value_name("ADMIN PASSWORD")
+                                            .help("The admin password of the server")
+                                            .takes_value(true)
+                                            .number_of_values(1)
+                                            .validator(key_password_validator))
+                                       .arg(Arg::with_name("server-address")
+                                            .index(4)
+                                            .short("a")
+                                            .long("server-address")
+                                            .value_name("SERVER ADDRESS")
+                                            .help("The address of the server, IP:PORT, HOSTNAME:PORT or unix:PATH")
+                                            .takes_value(true)
+                                            .number_of_values(1)
+                                            .validator(server_address_validator)))
+}
@@ -295,21 +295,27 @@ pub enum InsertionType {
     Insert
 }
 
+impl Default for ClientsMap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl ClientsMap {
     pub fn new() -> ClientsMap {
         ClientsMap { clients: collections::BTreeMap::new() }
     }
 
     pub fn insert(&mut self, mac: &ipparser::MacAddress, client: &Client) -> InsertionType {
-        if let Some(existing_client) = self.clients.get(&mac) { // MAC exists
+        if let Some(existing_client) = self.clients.get(mac) { // MAC exists
             if *existing_client == *client { // IPv4 also exists
                 // Do nothing... I think I should do an update here, maybe the client changed his name
                 self.clients.insert(mac.clone(), client.clone());
-                return InsertionType::Update;
+                InsertionType::Update
             } else { // IPv4 does not exist
                 // Do an update
                 self.clients.insert(mac.clone(), client.clone());
-                return InsertionType::Update;
+                InsertionType::Update
             }
         } else { // MAC does not exist            
             let repl_mac: ipparser::MacAddress;
@@ -323,7 +329,7 @@ impl ClientsMap {
             }            
             self.clients.remove(&repl_mac);
             self.clients.insert(mac.clone(), client.clone());
-            return InsertionType::Replace { client_mac_replaced: repl_mac };
+            InsertionType::Replace { client_mac_replaced: repl_mac }
         }
     }
 
@@ -331,11 +337,12 @@ impl ClientsMap {
         self.clients.len()
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.clients.is_empty()
+    }
+
     pub fn get_by_mac(&self, mac: &ipparser::MacAddress) -> Option<Client> {
-        match self.clients.get(mac) {
-            Some(client) => Some(client.clone()),
-            None => None
-        }
+        self.clients.get(mac).cloned()
     }
 
     pub fn exists_by_ipv4(&self, ipv4: u32) -> bool {
@@ -367,7 +374,7 @@ impl ClientsMap {
                 break;
             }
         }
-        return clients_range;
+        clients_range
     }
 
     pub fn usernames_that_contain(&self, start_index: usize, size: usize, pattern: &str) -> (Vec<Client>, usize) {
@@ -381,10 +388,10 @@ impl ClientsMap {
                 }                
             }
         }
-        if self.clients.len() > 0 {
-            return (clients, self.clients.len()-1);
+        if !self.clients.is_empty() {
+            (clients, self.clients.len()-1)
         } else {
-            return (clients, self.clients.len());
+            (clients, self.clients.len())
         }
     }
 
@@ -399,10 +406,10 @@ impl ClientsMap {
                 }
             }
         }
-        if self.clients.len() > 0 {
-            return (clients, self.clients.len()-1);
+        if !self.clients.is_empty() {
+            (clients, self.clients.len()-1)
         } else {
-            return (clients, self.clients.len());
+            (clients, self.clients.len())
         }
     }
 
@@ -412,17 +419,15 @@ impl ClientsMap {
             if index >= start_index {
                 if clients.len() == size {
                     return (clients, index);
-                } else if client.username_contains_ignore_case(pattern) {
-                    if !client.get_only_by_mac {
-                        clients.push(client.clone());
-                    }                    
-                }                
+                } else if client.username_contains_ignore_case(pattern) && !client.get_only_by_mac {
+                    clients.push(client.clone());
+                }
             }
         }
-        if self.clients.len() > 0 {
-            return (clients, self.clients.len()-1);
+        if !self.clients.is_empty() {
+            (clients, self.clients.len()-1)
         } else {
-            return (clients, self.clients.len());
+            (clients, self.clients.len())
         }
     }
 
@@ -444,7 +449,7 @@ impl ClientsMap {
         if let Some((mac_key, _client_value)) = self.clients.iter().find(|(_mac, client)| client.get_ipv4_addr() == ipv4) {
             mac = mac_key.clone();
         } else {  return false; }
-        return self.drop_vote_by_mac(&mac, drop_votes, max_drop_votes);
+        self.drop_vote_by_mac(&mac, drop_votes, max_drop_votes)
     }
 
     pub fn drop_by_ipv4(&mut self, ipv4: u32) -> bool {
@@ -454,10 +459,10 @@ impl ClientsMap {
         } else { return false; }
         
         if let Some(_client) = self.clients.remove(&mac) {
-            return true;
+            true
         } else {
             log::error!("clients::ClientsMap::drop_by_ipv4: client {} was not removed", mac);
-            return false;
+            false
         }
     }
 
@@ -469,7 +474,7 @@ impl ClientsMap {
             }
         }
         for (mac, _client) in clients.iter() {
-            if let Some(_c) = self.clients.remove(&mac) {
+            if let Some(_c) = self.clients.remove(mac) {
             } else {
                 log::error!("clients::ClientsMap::drop_amount: client {} was not removed", mac);
             }
@@ -5,14 +5,29 @@
 extern crate serde_json;
 extern crate log;
 extern crate clap;
+extern crate rand;
+use rand::Rng;
+use std::cmp;
 use std::net;
+#[cfg(unix)]
+use std::os::unix::net as unix_net;
+use std::path;
+use std::str::FromStr;
+use std::thread;
+use crate::config;
 use crate::ipparser;
+use crate::auth;
 use crate::clients;
+use crate::proxy;
+use crate::secret;
+use crate::server_config;
+use crate::tls;
+use crate::transport;
 use std::time;
-use std::io::{
-    Read,
-    Write
-};
+
+pub const DEFAULT_TIMEOUT: time::Duration = time::Duration::from_secs(10);
+const BACKOFF_BASE: time::Duration = time::Duration::from_millis(200);
+const BACKOFF_CAP: time::Duration = time::Duration::from_secs(5);
 
 pub enum Set {
     DropVotes(u8),
@@ -27,7 +42,8 @@ pub enum Get {
     Mac(ipparser::MacAddress),
     Username { pattern: String, start_index: usize },
     Index { start_index: usize, end_index: usize },
-    RunningConfiguration
+    // Only the setting when there is one, in the given format
+    RunningConfiguration { setting: Option<server_config::Setting>, format: server_config::ConfigFormat }
 }
 
 pub enum Drop {
@@ -40,106 +56,105 @@ pub enum Command {
     Drop(Drop)
 }
 
-pub struct BaseCommand {
-    password: String,
-    address: net::SocketAddrV4,
-    command: Command
-}
+impl Command {
+    // The name of the subcommand that builds this command
+    pub fn name(&self) -> &'static str {
+        match self {
+            Command::Set(Set::DropVotes(_)) => "set-dropvotes",
+            Command::Set(Set::DropVerification(_)) => "set-dropverification",
+            Command::Set(Set::ListSize(_)) => "set-listsize",
+            Command::Set(Set::Capacity(_)) => "set-capacity",
+            Command::Set(Set::Password(_)) => "set-password",
+            Command::Set(Set::Key(_)) => "set-key",
+            Command::Get(Get::Mac(_)) => "get-mac",
+            Command::Get(Get::Username { .. }) => "get-username",
+            Command::Get(Get::Index { .. }) => "get-index",
+            Command::Get(Get::RunningConfiguration { .. }) => "running-config",
+            Command::Drop(Drop::Ip(_)) => "drop"
+        }
+    }
 
-impl BaseCommand {
-    pub fn from_clap_matches(matches: &clap::ArgMatches) -> Option<BaseCommand> {
+    // Reads the command of the subcommand matches, the password and address are read apart
+    pub fn from_clap_matches(matches: &clap::ArgMatches) -> Option<Command> {
         if let Some(subcommand_matches) = matches.subcommand_matches("running-config") {
-            if let Some((password, address)) = password_and_address(subcommand_matches) {
-                return Some( BaseCommand { password, address, command: Command::Get(Get::RunningConfiguration) } );
-            }
+            let setting = match subcommand_matches.value_of("get") {
+                Some(setting) => Some(setting.parse::<server_config::Setting>().ok()?),
+                None => None
+            };
+            let format = subcommand_matches.value_of("format").unwrap_or("text").parse::<server_config::ConfigFormat>().ok()?;
+            return Some(Command::Get(Get::RunningConfiguration { setting, format }));
         } else if let Some(subcommand_matches) = matches.subcommand_matches("set-dropvotes") {
-            if let Some((password, address)) = password_and_address(subcommand_matches) {
-                if let Some(drop_votes) = subcommand_matches.value_of("drop-votes") {
-                    if let Ok(drop_votes) = drop_votes.parse::<u8>() {
-                        if drop_votes > 0 {
-                            return Some( BaseCommand { password, address, command: Command::Set(Set::DropVotes(drop_votes)) } );
-                        }
+            if let Some(drop_votes) = subcommand_matches.value_of("drop-votes") {
+                if let Ok(drop_votes) = drop_votes.parse::<u8>() {
+                    if drop_votes > 0 {
+                        return Some(Command::Set(Set::DropVotes(drop_votes)));
                     }
                 }
-            }            
+            }
         } else if let Some(subcommand_matches) = matches.subcommand_matches("set-dropverification") {
-            if let Some((password, address)) = password_and_address(subcommand_matches) {
-                if let Some(state) = subcommand_matches.value_of("state") {
-                    if let Ok(state) = state.parse::<bool>() {
-                        return Some( BaseCommand { password, address, command: Command::Set(Set::DropVerification(state)) } );
-                    }
+            if let Some(state) = subcommand_matches.value_of("state") {
+                if let Ok(state) = state.parse::<bool>() {
+                    return Some(Command::Set(Set::DropVerification(state)));
                 }
             }
         } else if let Some(subcommand_matches) = matches.subcommand_matches("set-listsize") {
-            if let Some((password, address)) = password_and_address(subcommand_matches) {
-                if let Some(list_size) = subcommand_matches.value_of("list-size") {
-                    if let Ok(list_size) = list_size.parse::<u16>() {
-                        return Some( BaseCommand { password, address, command: Command::Set(Set::ListSize(list_size)) } );
-                    }
+            if let Some(list_size) = subcommand_matches.value_of("list-size") {
+                if let Ok(list_size) = list_size.parse::<u16>() {
+                    return Some(Command::Set(Set::ListSize(list_size)));
                 }
             }
         } else if let Some(subcommand_matches) = matches.subcommand_matches("set-capacity") {
-            if let Some((password, address)) = password_and_address(subcommand_matches) {
-                if let Some(capacity) = subcommand_matches.value_of("capacity") {
-                    if let Ok(capacity) = capacity.parse::<u16>() {
-                        if capacity > 1 {
-                            return Some( BaseCommand { password, address, command: Command::Set(Set::Capacity(capacity)) } );
-                        }
+            if let Some(capacity) = subcommand_matches.value_of("capacity") {
+                if let Ok(capacity) = capacity.parse::<u16>() {
+                    if capacity > 1 {
+                        return Some(Command::Set(Set::Capacity(capacity)));
                     }
                 }
-            }            
+            }
         } else if let Some(subcommand_matches) = matches.subcommand_matches("set-key") {
-            if let Some((password, address)) = password_and_address(subcommand_matches) {
-                if let Some(key) = subcommand_matches.value_of("key") {
-                    if key.is_ascii() && key.len() < 33 {
-                        return Some( BaseCommand { password, address, command: Command::Set(Set::Key(key.to_string())) } );
-                    }
-                }
+            let key = match subcommand_matches.value_of("key") {
+                Some(key) => key.to_string(),
+                None => secret::from_clap_matches(subcommand_matches, secret::DEFAULT_KEY_LENGTH).ok()?
+            };
+            if key.is_ascii() && key.len() < 33 {
+                return Some(Command::Set(Set::Key(key)));
             }
         } else if let Some(subcommand_matches) = matches.subcommand_matches("set-password") {
-            if let Some((password, address)) = password_and_address(subcommand_matches) {
-                if let Some(new_password) = subcommand_matches.value_of("password") {
-                    if new_password.is_ascii() && new_password.len() < 33 {
-                        return Some( BaseCommand { password, address, command: Command::Set(Set::Password(new_password.to_string())) } );
-                    }
-                }
+            let new_password = match subcommand_matches.value_of("password") {
+                Some(new_password) => new_password.to_string(),
+                None => secret::from_clap_matches(subcommand_matches, secret::DEFAULT_PASSWORD_LENGTH).ok()?
+            };
+            if new_password.is_ascii() && new_password.len() < 33 {
+                return Some(Command::Set(Set::Password(new_password)));
             }
         } else if let Some(subcommand_matches) = matches.subcommand_matches("drop") {
-            if let Some((password, address)) = password_and_address(subcommand_matches) {
-                if let Some(ip) = subcommand_matches.value_of("ip") {
-                    if let Some(ip) = ipparser::str_to_ipv4addr(ip) {
-                        return Some( BaseCommand { password, address, command: Command::Drop(Drop::Ip(ip)) } );
-                    }
+            if let Some(ip) = subcommand_matches.value_of("ip") {
+                if let Some(ip) = ipparser::str_to_ipv4addr(ip) {
+                    return Some(Command::Drop(Drop::Ip(ip)));
                 }
             }
         } else if let Some(subcommand_matches) = matches.subcommand_matches("get-mac") {
-            if let Some((password, address)) = password_and_address(subcommand_matches) {
-                if let Some(mac) = subcommand_matches.value_of("mac") {
-                    if let Some(mac) = ipparser::MacAddress::new_from_str(mac) {
-                        return Some( BaseCommand { password, address, command: Command::Get(Get::Mac(mac)) } );
-                    }
+            if let Some(mac) = subcommand_matches.value_of("mac") {
+                if let Some(mac) = ipparser::MacAddress::new_from_str(mac) {
+                    return Some(Command::Get(Get::Mac(mac)));
                 }
             }
         } else if let Some(subcommand_matches) = matches.subcommand_matches("get-username") {
-            if let Some((password, address)) = password_and_address(subcommand_matches) {
-                if let Some(pattern) = subcommand_matches.value_of("pattern") {
-                    if pattern.is_ascii() {
-                        if let Some(start_index) = subcommand_matches.value_of("start") {
-                            if let Ok(start_index) = start_index.parse::<usize>() {
-                                return Some( BaseCommand { password, address, command: Command::Get(Get::Username { pattern: pattern.to_string(), start_index } ) } );
-                            }
+            if let Some(pattern) = subcommand_matches.value_of("pattern") {
+                if pattern.is_ascii() {
+                    if let Some(start_index) = subcommand_matches.value_of("start") {
+                        if let Ok(start_index) = start_index.parse::<usize>() {
+                            return Some(Command::Get(Get::Username { pattern: pattern.to_string(), start_index }));
                         }
                     }
                 }
             }
         } else if let Some(subcommand_matches) = matches.subcommand_matches("get-index") {
-            if let Some((password, address)) = password_and_address(subcommand_matches) {
-                if let Some(start_index) = subcommand_matches.value_of("start") {
-                    if let Ok(start_index) = start_index.parse::<usize>() {
-                        if let Some(end_index) = subcommand_matches.value_of("end") {
-                            if let Ok(end_index) = end_index.parse::<usize>() {
-                                return Some( BaseCommand { password, address, command: Command::Get(Get::Index { start_index, end_index }) } );
-                            }
+            if let Some(start_index) = subcommand_matches.value_of("start") {
+                if let Ok(start_index) = start_index.parse::<usize>() {
+                    if let Some(end_index) = subcommand_matches.value_of("end") {
+                        if let Ok(end_index) = end_index.parse::<usize>() {
+                            return Some(Command::Get(Get::Index { start_index, end_index }));
                         }
                     }
                 }
@@ -148,74 +163,292 @@ impl BaseCommand {
         None
     }
 
-    pub fn to_json_string(&self) -> String {
-        let mut json_string = format!("{{\"user\":\"admin\",\"password\":\"{}\",", self.password);
-        match &self.command {
+    // The fields that say what to do, e.g. {"method":"set","what":"capacity","capacity":10}
+    pub fn to_json_value(&self) -> serde_json::Value {
+        match self {
             Command::Set(set) => {
-                json_string.push_str("\"method\":\"set\",");
                 match set {
-                    Set::Capacity(capacity) => {
-                        json_string.push_str("\"what\":\"capacity\",");
-                        json_string.push_str(&format!("\"capacity\":{}", capacity));
-                    },
-                    Set::DropVerification(dv) => {
-                        json_string.push_str("\"what\":\"drop_verification\",");
-                        json_string.push_str(&format!("\"drop_verification\":{}", dv));
-                    },
-                    Set::DropVotes(dv) => {
-                        json_string.push_str("\"what\":\"drop_votes\",");
-                        json_string.push_str(&format!("\"drop_votes\":{}", dv));
-                    },
-                    Set::Key(key) => {
-                        json_string.push_str("\"what\":\"key\",");
-                        json_string.push_str(&format!("\"key\":\"{}\"", key));
-                    },
-                    Set::ListSize(ls) => {
-                        json_string.push_str("\"what\":\"list_size\",");
-                        json_string.push_str(&format!("\"list_size\":{}", ls));
-                    },
-                    Set::Password(password) => {
-                        json_string.push_str("\"what\":\"password\",");
-                        json_string.push_str(&format!("\"new_password\":\"{}\"", password));
-                    }
+                    Set::Capacity(capacity) => serde_json::json!({ "method": "set", "what": "capacity", "capacity": capacity }),
+                    Set::DropVerification(dv) => serde_json::json!({ "method": "set", "what": "drop_verification", "drop_verification": dv }),
+                    Set::DropVotes(dv) => serde_json::json!({ "method": "set", "what": "drop_votes", "drop_votes": dv }),
+                    Set::Key(key) => serde_json::json!({ "method": "set", "what": "key", "key": key }),
+                    Set::ListSize(ls) => serde_json::json!({ "method": "set", "what": "list_size", "list_size": ls }),
+                    Set::Password(password) => serde_json::json!({ "method": "set", "what": "password", "new_password": password })
                 }
             },
             Command::Get(get) => {
-                json_string.push_str("\"method\":\"get\",");
                 match get {
                     Get::Index { start_index, end_index } => {
-                        json_string.push_str("\"how\":\"index\",");
-                        json_string.push_str(&format!("\"start_index\":{},\"end_index\":{}", start_index, end_index));
-                    },
-                    Get::Mac(mac) => {
-                        json_string.push_str("\"how\":\"mac\",");
-                        json_string.push_str(&format!("\"mac\":\"{}\"", mac));
-                    },
-                    Get::RunningConfiguration => {
-                        json_string.push_str("\"how\":\"running_configuration\"");
+                        serde_json::json!({ "method": "get", "how": "index", "start_index": start_index, "end_index": end_index })
                     },
+                    Get::Mac(mac) => serde_json::json!({ "method": "get", "how": "mac", "mac": mac.to_string() }),
+                    Get::RunningConfiguration { .. } => serde_json::json!({ "method": "get", "how": "running_configuration" }),
                     Get::Username { pattern, start_index } => {
-                        json_string.push_str("\"how\":\"username\",");
-                        json_string.push_str(&format!("\"username\":\"{}\",\"start_index\":{}", pattern, start_index));
+                        serde_json::json!({ "method": "get", "how": "username", "username": pattern, "start_index": start_index })
                     }
                 }
             },
             Command::Drop(drop) => {
-                json_string.push_str("\"method\":\"drop\",");
                 match drop {
-                    Drop::Ip(ip) => {
-                        json_string.push_str(&format!("\"ip\":\"{}\"", ip));
+                    Drop::Ip(ip) => serde_json::json!({ "method": "drop", "ip": ip.to_string() })
+                }
+            }
+        }
+    }
+
+    // Only the get commands are retried, a lost reply to a drop or a set-dropvotes does not mean
+    // that the server did not apply it.
+    pub fn is_idempotent(&self) -> bool {
+        matches!(self, Command::Get(_))
+    }
+}
+
+// How the admin password is proven: sent in every request, or used to answer a challenge of the
+// server so it never crosses the wire (see the auth module)
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum AuthMode {
+    Password,
+    Challenge
+}
+
+impl FromStr for AuthMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<AuthMode, String> {
+        match s {
+            "password" => Ok(AuthMode::Password),
+            "challenge" => Ok(AuthMode::Challenge),
+            _ => Err(format!("{} is not a valid authentication mode, use password or challenge", s))
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct ConnectionOptions {
+    pub connect_timeout: Option<time::Duration>,
+    pub timeout: time::Duration,
+    pub retries: u32,
+    pub tls: Option<tls::TlsOptions>,
+    pub proxy: Option<proxy::Proxy>,
+    pub auth: AuthMode,
+    // Add a timestamp, a nonce and an HMAC-SHA256 signature to every request, so a captured
+    // request can not be replayed
+    pub sign: bool
+}
+
+impl Default for ConnectionOptions {
+    fn default() -> Self {
+        ConnectionOptions { connect_timeout: None, timeout: DEFAULT_TIMEOUT, retries: 0, tls: None, proxy: None, auth: AuthMode::Password, sign: false }
+    }
+}
+
+impl ConnectionOptions {
+    // The command line values take precedence over the ones of the profile, and the proxy of the
+    // profile over the one of the environment
+    pub fn from_clap_matches(matches: &clap::ArgMatches, profile: Option<&config::Profile>) -> Option<ConnectionOptions> {
+        let mut options = ConnectionOptions { proxy: proxy::Proxy::from_env(), ..ConnectionOptions::default() };
+        if let Some(profile) = profile {
+            if let Some(connect_timeout) = profile.connect_timeout {
+                options.connect_timeout = Some(seconds_to_duration(connect_timeout)?);
+            }
+            if let Some(timeout) = profile.timeout {
+                options.timeout = seconds_to_duration(timeout)?;
+            }
+            if let Some(retries) = profile.retries {
+                options.retries = retries;
+            }
+            options.tls = tls_options(profile.tls.unwrap_or(false), &profile.tls_ca_file, &profile.tls_client_cert,
+                                      &profile.tls_client_key, &profile.tls_fingerprint, &profile.tls_server_name)?;
+            if let Some(proxy) = &profile.proxy {
+                options.proxy = Some(proxy::Proxy::new_from_str(proxy)?);
+            }
+            if let Some(auth) = &profile.auth {
+                options.auth = auth.parse::<AuthMode>().ok()?;
+            }
+            options.sign = profile.sign.unwrap_or(false);
+        }
+        if let Some(connect_timeout) = config::global_value_of(matches, "connect-timeout") {
+            options.connect_timeout = Some(seconds_to_duration(connect_timeout.parse::<f64>().ok()?)?);
+        }
+        if let Some(timeout) = config::global_value_of(matches, "timeout") {
+            options.timeout = seconds_to_duration(timeout.parse::<f64>().ok()?)?;
+        }
+        if let Some(retries) = config::global_value_of(matches, "retries") {
+            options.retries = retries.parse::<u32>().ok()?;
+        }
+        if let Some(proxy) = config::global_value_of(matches, "proxy") {
+            options.proxy = Some(proxy::Proxy::new_from_str(proxy)?);
+        }
+        if let Some(auth) = config::global_value_of(matches, "auth") {
+            options.auth = auth.parse::<AuthMode>().ok()?;
+        }
+        if config::global_is_present(matches, "sign") {
+            options.sign = true;
+        }
+        let value_of = |name| config::global_value_of(matches, name).map(String::from);
+        if let Some(cli_tls) = tls_options(config::global_is_present(matches, "tls"), &value_of("tls-ca-file"), &value_of("tls-client-cert"),
+                                           &value_of("tls-client-key"), &value_of("tls-fingerprint"), &value_of("tls-server-name"))? {
+            let mut merged = options.tls.unwrap_or_default();
+            merged.ca_file = cli_tls.ca_file.or(merged.ca_file);
+            merged.client_cert = cli_tls.client_cert.or(merged.client_cert);
+            merged.client_key = cli_tls.client_key.or(merged.client_key);
+            merged.fingerprint = cli_tls.fingerprint.or(merged.fingerprint);
+            merged.server_name = cli_tls.server_name.or(merged.server_name);
+            options.tls = Some(merged);
+        }
+        Some(options)
+    }
+
+    // The proxy to reach host through, None when it is reached directly
+    pub fn proxy_for(&self, host: &str) -> Option<&proxy::Proxy> {
+        self.proxy.as_ref().filter(|proxy| !proxy.is_bypassed(host))
+    }
+
+    // Exponential backoff with full jitter: a random wait in [0, min(cap, base * 2^(retry - 1))]
+    pub fn backoff(&self, retry: u32) -> time::Duration {
+        let exponential = BACKOFF_BASE.checked_mul(1 << cmp::min(retry.saturating_sub(1), 16)).unwrap_or(BACKOFF_CAP);
+        let ceiling = cmp::min(exponential, BACKOFF_CAP);
+        ceiling.mul_f64(rand::thread_rng().gen::<f64>())
+    }
+}
+
+// TLS is used when it is enabled or when any of its settings is given. None inside Some means that
+// a setting is invalid.
+fn tls_options(enabled: bool, ca_file: &Option<String>, client_cert: &Option<String>, client_key: &Option<String>,
+               fingerprint: &Option<String>, server_name: &Option<String>) -> Option<Option<tls::TlsOptions>> {
+    if !enabled && ca_file.is_none() && client_cert.is_none() && client_key.is_none() && fingerprint.is_none() && server_name.is_none() {
+        return Some(None);
+    }
+    let fingerprint = match fingerprint {
+        Some(fingerprint) => Some(tls::str_to_fingerprint(fingerprint)?),
+        None => None
+    };
+    Some(Some(tls::TlsOptions {
+        ca_file: ca_file.as_ref().map(path::PathBuf::from),
+        client_cert: client_cert.as_ref().map(path::PathBuf::from),
+        client_key: client_key.as_ref().map(path::PathBuf::from),
+        fingerprint,
+        server_name: server_name.clone()
+    }))
+}
+
+fn seconds_to_duration(seconds: f64) -> Option<time::Duration> {
+    if seconds.is_finite() && seconds > 0.0 {
+        return Some(time::Duration::from_secs_f64(seconds));
+    }
+    None
+}
+
+pub struct BaseCommand {
+    password: String,
+    address: ipparser::ServerAddress,
+    command: Command,
+    options: ConnectionOptions
+}
+
+impl BaseCommand {
+    pub fn new(password: String, address: ipparser::ServerAddress, command: Command, options: ConnectionOptions) -> BaseCommand {
+        BaseCommand { password, address, command, options }
+    }
+
+    pub fn command(&self) -> &Command {
+        &self.command
+    }
+
+    pub fn address(&self) -> &ipparser::ServerAddress {
+        &self.address
+    }
+
+    pub fn options(&self) -> &ConnectionOptions {
+        &self.options
+    }
+
+    pub fn password(&self) -> &str {
+        &self.password
+    }
+
+    pub fn from_clap_matches(matches: &clap::ArgMatches, profile: Option<&config::Profile>) -> Option<BaseCommand> {
+        if let (_name, Some(subcommand_matches)) = matches.subcommand() {
+            if let Some((password, address)) = password_and_address(subcommand_matches, profile) {
+                if let Some(command) = Command::from_clap_matches(matches) {
+                    if let Some(options) = ConnectionOptions::from_clap_matches(matches, profile) {
+                        return Some(BaseCommand { password, address, command, options });
                     }
                 }
             }
         }
-        json_string.push('}');
-        return json_string;
+        None
+    }
+
+    pub fn to_json_string(&self) -> String {
+        auth::canonical_json(&self.request_value(Some(&self.password)))
+    }
+
+    // The request without the password, the body that is signed when the challenge is answered
+    pub fn body_json_string(&self) -> String {
+        auth::canonical_json(&self.request_value(None))
+    }
+
+    // The signed request that answers the nonce of a challenge reply, None when the server
+    // did not send a nonce
+    pub fn challenge_response(&self, reply: &str) -> Option<String> {
+        let reply = serde_json::from_str::<serde_json::Value>(reply).ok()?;
+        let nonce = reply.get("nonce")?.as_str()?;
+        let body = self.body_json_string();
+        let signature = auth::sign(&self.password, nonce, &body);
+        Some(serde_json::json!({ "user": "admin", "auth": auth::CHALLENGE_AUTH, "request": body, "hmac": signature }).to_string())
+    }
+
+    // The typed request, signed when it was asked for. The signature never covers the password,
+    // so the same body is signed in both authentication modes.
+    fn request_value(&self, password: Option<&str>) -> serde_json::Value {
+        let mut request = self.command.to_json_value();
+        if let Some(fields) = request.as_object_mut() {
+            fields.insert(String::from("user"), serde_json::Value::from("admin"));
+            if self.options.sign {
+                auth::sign_request(fields, &self.password);
+            }
+            if let Some(password) = password {
+                fields.insert(String::from("password"), serde_json::Value::from(password));
+            }
+        }
+        request
     }
 
     pub fn send_and_interpret(&self) -> String {
+        self.interpret(self.send())
+    }
+
+    // The reply of the server as JSON, for the subcommands that act on what the server replied.
+    // An error reply or no reply at all is an Err with the reason.
+    pub fn send_and_parse(&self) -> Result<serde_json::Value, String> {
+        self.parse(self.send())
+    }
+
+    // Like interpret, None means that there was no reply
+    pub fn parse(&self, reply: Option<String>) -> Result<serde_json::Value, String> {
+        let reply = reply.ok_or(format!("No answer from {}", self.address))?;
+        let reply = serde_json::from_str::<serde_json::Value>(&reply)
+            .map_err(|_| format!("Could not parse the reply of {}, raw reply:\n{}", self.address, reply))?;
+        if let Some(error) = reply.get("error") {
+            let name = reply.get("name").and_then(|name| name.as_str()).unwrap_or("unknown error");
+            return Err(format!("{} replied error code {}: {}", self.address, error, name));
+        }
+        match reply.get("result").and_then(|result| result.as_str()) {
+            Some("ok") => Ok(reply),
+            _ => Err(format!("Could not parse the reply of {} as a valid MINT server reply, raw reply:\n{}", self.address, reply))
+        }
+    }
+
+    // Sends the command through a transport opened by the caller, e.g. a transport::MemoryTransport
+    pub fn send_and_interpret_with(&self, transport: &mut dyn transport::Transport) -> String {
+        self.interpret(self.exchange(transport))
+    }
+
+    // Renders the reply of the server, None means that there was no reply
+    pub fn interpret(&self, reply: Option<String>) -> String {
         let mut string = String::default();
-        if let Some(answer) = self.send() {
+        if let Some(answer) = reply {
             if let Ok(answer) = serde_json::from_str::<serde_json::Value>(&answer) {
                 if let Some(result) = answer.get("result") {
                     if let Some(result) = result.as_str() {
@@ -290,11 +523,21 @@ impl BaseCommand {
                                             }
                                         }
                                     },
-                                    Get::RunningConfiguration => {
-                                        if let Some(run_conf) = answer.get("running_config") {
-                                            string.push_str(&format!("\n{}", run_conf.as_str().unwrap()));
+                                    Get::RunningConfiguration { setting: None, format: server_config::ConfigFormat::Text } => {
+                                        // Shown as the server sent it, it can have settings this version does not know
+                                        match answer.get("running_config") {
+                                            Some(serde_json::Value::String(run_conf)) => string.push_str(&format!("\n{}", run_conf)),
+                                            Some(_) => return format!("Could not parse the reply of {} as a valid MINT server reply, raw reply:\n{}", self.address, answer),
+                                            None => {}
                                         }
                                     },
+                                    Get::RunningConfiguration { setting, format } => {
+                                        // Only the value, so scripts can read it
+                                        return match server_config::ServerConfig::from_reply(&answer) {
+                                            Ok(config) => config.render(*setting, *format),
+                                            Err(e) => format!("Could not parse the running config of {}: {}, raw reply:\n{}", self.address, e, answer)
+                                        };
+                                    },
                                     Get::Username { pattern: _p, start_index: _s } => {
                                         if let Some(clients) = answer.get("clients") {
                                             if let Some(clients) = clients.as_array() {
@@ -353,53 +596,252 @@ impl BaseCommand {
         } else {
             string.push_str(&format!("No answer from {}", self.address));
         }
-        return string;
-    }
-
-    fn send(&self) -> Option<String> {
-        log::debug!("Connecting with {} ...", self.address);
-        if let Ok(mut client) = net::TcpStream::connect(self.address) {
-            log::debug!("Connection established with {}", self.address);
-            let request = self.to_json_string();
-            log::debug!("request:\n{}", request);
-            if let Ok(bytes_written) = client.write(request.as_bytes()) {
-                if bytes_written == request.as_bytes().len() {
-                    log::info!("The request was sent succesfully [{} byte(s)]", bytes_written);
-                    let mut buffer = [0; 65535];
-                    if let Ok(()) = client.set_read_timeout(Some(time::Duration::from_secs(10))) {
-                        log::info!("Read timeout was set to 10 seconds");
-                    } else {
-                        log::warn!("Could not set read timeout to 10 seconds");
-                    }
-                    if let Ok(bytes_received) = client.read(&mut buffer) {
-                        log::info!("{} byte(s) received", bytes_received);
-                        let reply = String::from_utf8_lossy(&buffer[..bytes_received]);
-                        log::debug!("raw reply received:\n{}", reply);
-                        return Some(reply.to_string());
-                    } else {
-                        log::error!("{} didn't reply anything", self.address);
+        string
+    }
+
+    // The raw reply, None when there was no answer
+    pub fn send(&self) -> Option<String> {
+        let retries = if self.command.is_idempotent() { self.options.retries } else { 0 };
+        for retry in 0..=retries {
+            if retry > 0 {
+                let backoff = self.options.backoff(retry);
+                log::warn!(server:% = self.address, command = self.command.name(); "Retrying in {} ms [{} of {}]", backoff.as_millis(), retry, retries);
+                thread::sleep(backoff);
+            }
+            if let Some(reply) = self.send_once() {
+                return Some(reply);
+            }
+        }
+        None
+    }
+
+    fn send_once(&self) -> Option<String> {
+        let mut transport = self.open_transport()?;
+        self.exchange(transport.as_mut())
+    }
+
+    fn exchange(&self, transport: &mut dyn transport::Transport) -> Option<String> {
+        match self.options.auth {
+            AuthMode::Password => self.round_trip(transport, &self.to_json_string()),
+            AuthMode::Challenge => {
+                let reply = self.round_trip(transport, auth::CHALLENGE_REQUEST)?;
+                match self.challenge_response(&reply) {
+                    Some(signed_request) => self.round_trip(transport, &signed_request),
+                    // The server refused to send a nonce, its reply says why
+                    None => Some(reply)
+                }
+            }
+        }
+    }
+
+    fn round_trip(&self, transport: &mut dyn transport::Transport, request: &str) -> Option<String> {
+        let command = self.command.name();
+        let started = time::Instant::now();
+        log::debug!("request:\n{}", request);
+        if let Err(e) = transport.send(request.as_bytes()) {
+            log::error!(server:% = self.address, command; "The request could not be sent to {}: {}", self.address, e);
+            return None;
+        }
+        log::info!(server:% = self.address, command; "The request was sent succesfully [{} byte(s)]", request.len());
+        match transport.receive() {
+            Ok(reply) => {
+                let latency_ms = started.elapsed().as_millis() as u64;
+                log::info!(server:% = self.address, command, latency_ms; "{} byte(s) received", reply.len());
+                let reply = String::from_utf8_lossy(&reply).to_string();
+                log::debug!("raw reply received:\n{}", reply);
+                Some(reply)
+            },
+            Err(e) => {
+                log::error!(server:% = self.address, command; "{} didn't reply anything: {}", self.address, e);
+                None
+            }
+        }
+    }
+
+    pub(crate) fn open_transport(&self) -> Option<Box<dyn transport::Transport>> {
+        match self.options.connect(&self.address, self.command.name())? {
+            Connection::Tcp(stream, host) => match &self.options.tls {
+                Some(tls_options) => match transport::TlsTransport::connect(transport::TcpTransport::new(stream), tls_options, &host) {
+                    Ok(tls_transport) => Some(Box::new(tls_transport)),
+                    Err(e) => {
+                        log::error!(server:% = self.address, command = self.command.name(); "{}", e);
+                        None
                     }
-                } else {
-                    log::error!("{} of {} byte(s) were sent to {}", bytes_written, request.as_bytes().len(), self.address);
+                },
+                None => Some(Box::new(transport::TcpTransport::new(stream)))
+            },
+            #[cfg(unix)]
+            Connection::Unix(stream) => Some(Box::new(transport::UnixTransport::new(stream)))
+        }
+    }
+}
+
+// A connection with the server before TLS, opened the same way by the blocking and the async
+// clients
+pub(crate) enum Connection {
+    // With the host of the server address, the name the TLS certificate is checked against
+    Tcp(net::TcpStream, String),
+    #[cfg(unix)]
+    Unix(unix_net::UnixStream)
+}
+
+impl ConnectionOptions {
+    // Directly or through the proxy, the read and write timeouts of the stream are set
+    pub(crate) fn connect(&self, address: &ipparser::ServerAddress, command: &str) -> Option<Connection> {
+        match address {
+            ipparser::ServerAddress::Inet(host_and_port) => {
+                let stream = match self.proxy_for(host_and_port.host()) {
+                    Some(proxy) => self.connect_through_proxy(address, proxy, host_and_port, command)?,
+                    None => self.connect_directly(address, host_and_port, command)?
+                };
+                Some(Connection::Tcp(stream, host_and_port.host().to_string()))
+            },
+            ipparser::ServerAddress::Unix(socket_path) => self.connect_unix(address, socket_path, command)
+        }
+    }
+
+    // Tries every address the server address resolves to, in the resolver order
+    fn connect_directly(&self, address: &ipparser::ServerAddress, host_and_port: &ipparser::HostAndPort, command: &str) -> Option<net::TcpStream> {
+        let resolved_addresses = match host_and_port.resolve() {
+            Ok(resolved_addresses) => resolved_addresses,
+            Err(e) => {
+                log::error!(server:% = address, command; "Could not resolve {}: {}", address, e);
+                return None;
+            }
+        };
+        for resolved in resolved_addresses.iter() {
+            log::info!(server:% = address, command, resolved:% = resolved; "Connecting with {} ({}) ...", address, resolved);
+            match transport::TcpTransport::connect(resolved, self.connect_timeout, self.timeout) {
+                Ok(tcp_transport) => {
+                    log::info!(server:% = address, command, resolved:% = resolved; "Connection established with {}", resolved);
+                    return Some(tcp_transport.into_inner());
+                },
+                Err(e) => {
+                    log::warn!(server:% = address, command, resolved:% = resolved; "Could not connect to {}: {}", resolved, e);
                 }
-            } else {
-                log::error!("No bytes were sent to {}", self.address);
             }
-            
-        } else {
-            log::error!("Could not connect to {}", self.address);
         }
+        log::error!(server:% = address, command; "Could not connect to {}", address);
+        None
+    }
+
+    // The proxy resolves the server name, so servers only known inside the bastion network work
+    fn connect_through_proxy(&self, address: &ipparser::ServerAddress, proxy: &proxy::Proxy, host_and_port: &ipparser::HostAndPort,
+                             command: &str) -> Option<net::TcpStream> {
+        match proxy.connect(host_and_port, self.connect_timeout, self.timeout) {
+            Ok(stream) => Some(stream),
+            Err(e) => {
+                log::error!(server:% = address, command, proxy:% = proxy; "Could not connect to {} through {}: {}", address, proxy, e);
+                None
+            }
+        }
+    }
+
+    // The socket file permissions protect the admin interface, so TLS is not used here
+    #[cfg(unix)]
+    fn connect_unix(&self, address: &ipparser::ServerAddress, socket_path: &path::Path, command: &str) -> Option<Connection> {
+        if self.tls.is_some() {
+            log::error!(server:% = address, command; "TLS can not be used with the Unix socket {}", socket_path.display());
+            return None;
+        }
+        log::info!(server:% = address, command; "Connecting with {} ...", address);
+        match transport::UnixTransport::connect(socket_path, self.timeout) {
+            Ok(unix_transport) => {
+                log::info!(server:% = address, command; "Connection established with {}", address);
+                Some(Connection::Unix(unix_transport.into_inner()))
+            },
+            Err(e) => {
+                log::error!(server:% = address, command; "Could not connect to {}: {}", address, e);
+                None
+            }
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn connect_unix(&self, address: &ipparser::ServerAddress, _socket_path: &path::Path, command: &str) -> Option<Connection> {
+        log::error!(server:% = address, command; "Unix sockets are not supported in this platform");
         None
     }
 }
 
-fn password_and_address(matches: &clap::ArgMatches) -> Option<(String, net::SocketAddrV4)> {
-    if let Some(password) = matches.value_of("admin-password") {
-        if let Some(address) = matches.value_of("server-address") {
-            if let Some(address) = ipparser::sockaddrv4str_to_sockaddrv4(address) {
+pub fn has_password_and_address(matches: &clap::ArgMatches, profile: Option<&config::Profile>) -> bool {
+    if let (_name, Some(subcommand_matches)) = matches.subcommand() {
+        return password_and_address(subcommand_matches, profile).is_some();
+    }
+    false
+}
+
+// The command line values take precedence over the ones of the profile
+fn password_and_address(matches: &clap::ArgMatches, profile: Option<&config::Profile>) -> Option<(String, ipparser::ServerAddress)> {
+    let password = matches.value_of("admin-password").or_else(|| profile.and_then(|p| p.admin_password.as_deref()));
+    let address = matches.value_of("server-address").or_else(|| profile.and_then(|p| p.server_address.as_deref()));
+    if let Some(password) = password {
+        if let Some(address) = address {
+            if let Some(address) = ipparser::ServerAddress::new_from_str(address) {
                 return Some((password.to_string(), address));
             }
         }
     }
     None
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::io::Write;
+    use std::sync;
+    use std::sync::atomic;
+
+    // A server that closes the first `drops` connections without replying and answers the rest,
+    // returns its address and how many connections it accepted
+    fn dropping_server(drops: usize) -> (String, sync::Arc<atomic::AtomicUsize>) {
+        let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let connections = sync::Arc::new(atomic::AtomicUsize::new(0));
+        let accepted = sync::Arc::clone(&connections);
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut request = [0u8; 1024];
+                let _ = stream.read(&mut request);
+                if accepted.fetch_add(1, atomic::Ordering::SeqCst) >= drops {
+                    let _ = stream.write_all(br#"{"result":"ok","clients":[]}"#);
+                }
+            }
+        });
+        (address, connections)
+    }
+
+    fn base_command(address: &str, command: Command) -> BaseCommand {
+        let options = ConnectionOptions { retries: 2, timeout: time::Duration::from_secs(2), ..ConnectionOptions::default() };
+        BaseCommand::new(String::from("admin"), ipparser::ServerAddress::new_from_str(address).unwrap(), command, options)
+    }
+
+    #[test]
+    fn backoff_is_capped() {
+        let options = ConnectionOptions::default();
+        for retry in 1..40 {
+            let ceiling = cmp::min(BACKOFF_BASE * 2u32.pow(cmp::min(retry - 1, 16)), BACKOFF_CAP);
+            assert!(options.backoff(retry) <= ceiling);
+        }
+    }
+
+    #[test]
+    fn gets_are_retried_after_a_dropped_connection() {
+        let (address, connections) = dropping_server(1);
+        let command = base_command(&address, Command::Get(Get::Index { start_index: 0, end_index: 1 }));
+        assert_eq!(command.send().as_deref(), Some(r#"{"result":"ok","clients":[]}"#));
+        assert_eq!(connections.load(atomic::Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn drops_and_drop_votes_are_never_retried() {
+        for command in [Command::Drop(Drop::Ip(net::Ipv4Addr::new(10, 0, 0, 1))), Command::Set(Set::DropVotes(2))] {
+            assert!(!command.is_idempotent());
+            let (address, connections) = dropping_server(usize::MAX);
+            assert_eq!(base_command(&address, command).send(), None);
+            assert_eq!(connections.load(atomic::Ordering::SeqCst), 1);
+        }
+    }
+}
@@ -1,26 +1,546 @@
 // Author: Jorge Alarcon Alvarez
 // Email:  jorge4larcon@gmail.com
-// This module is used to configure the application logging.
+// This module is used to configure the application logging and to load the server profiles.
 
 extern crate fern;
 extern crate log;
+extern crate chrono;
+extern crate serde;
+extern crate serde_json;
+extern crate clap;
+extern crate toml;
 
-pub fn setup_logging(log_level: &log::LevelFilter) -> Result<(), fern::InitError> {
-    let colors = fern::colors::ColoredLevelConfig::new().info(fern::colors::Color::Green)
-                                                        .warn(fern::colors::Color::Yellow)
-                                                        .error(fern::colors::Color::Red)
-                                                        .debug(fern::colors::Color::Blue)
-                                                        .trace(fern::colors::Color::Magenta);
-    fern::Dispatch::new()
-        .format(move |out, message, record| {
-            out.finish(format_args!(
-                "{}: {}",
-                colors.color(record.level()),
-                message
-            ))
-        })
-        .level(*log_level)
-        .chain(std::io::stdout())        
-        .apply()?;        
+use serde::Deserialize;
+use std::cmp;
+use std::collections;
+use std::env;
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::path;
+use std::str::FromStr;
+
+pub const DEFAULT_LOG_MAX_FILE_SIZE: u64 = 1024 * 1024;
+pub const DEFAULT_LOG_MAX_FILES: usize = 5;
+pub const PROFILES_PATH_VAR: &str = "ADMINT_CONFIG";
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum LogFormat {
+    Text,
+    Json
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<LogFormat, String> {
+        match s {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(format!("{} is not a valid log format, use text or json", s))
+        }
+    }
+}
+
+pub struct LogConfig {
+    pub level: log::LevelFilter,
+    pub format: LogFormat,
+    pub file: Option<path::PathBuf>,
+    pub max_file_size: u64,
+    pub max_files: usize
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        LogConfig {
+            level: log::LevelFilter::Warn,
+            format: LogFormat::Text,
+            file: None,
+            max_file_size: DEFAULT_LOG_MAX_FILE_SIZE,
+            max_files: DEFAULT_LOG_MAX_FILES
+        }
+    }
+}
+
+impl LogConfig {
+    // The logging flags are global, so they can appear before or after the subcommand name, clap
+    // copies the ones given before it into the subcommand matches.
+    pub fn from_clap_matches(matches: &clap::ArgMatches) -> Option<LogConfig> {
+        let mut log_config = LogConfig::default();
+        let mut all_matches = vec![matches];
+        if let (_name, Some(subcommand_matches)) = matches.subcommand() {
+            all_matches.push(subcommand_matches);
+        }
+        let mut verbose = 0;
+        let mut quiet = 0;
+        for m in all_matches {
+            verbose = cmp::max(verbose, m.occurrences_of("verbose"));
+            quiet = cmp::max(quiet, m.occurrences_of("quiet"));
+            if let Some(format) = m.value_of("log-format") {
+                log_config.format = format.parse::<LogFormat>().ok()?;
+            }
+            if let Some(file) = m.value_of("log-file") {
+                log_config.file = Some(path::PathBuf::from(file));
+            }
+            if let Some(max_file_size) = m.value_of("log-max-size") {
+                log_config.max_file_size = max_file_size.parse::<u64>().ok()?;
+            }
+            if let Some(max_files) = m.value_of("log-max-files") {
+                log_config.max_files = max_files.parse::<usize>().ok()?;
+            }
+        }
+        log_config.level = level_from_verbosity(verbose, quiet);
+        Some(log_config)
+    }
+}
+
+// -q shows only errors, -qq shows nothing, -v shows info, -vv debug and -vvv trace.
+pub fn level_from_verbosity(verbose: u64, quiet: u64) -> log::LevelFilter {
+    if quiet > 1 {
+        log::LevelFilter::Off
+    } else if quiet == 1 {
+        log::LevelFilter::Error
+    } else {
+        match verbose {
+            0 => log::LevelFilter::Warn,
+            1 => log::LevelFilter::Info,
+            2 => log::LevelFilter::Debug,
+            _ => log::LevelFilter::Trace
+        }
+    }
+}
+
+pub fn setup_logging(log_config: &LogConfig) -> Result<(), fern::InitError> {
+    let mut dispatch = fern::Dispatch::new()
+        .level(log_config.level)
+        .chain(stderr_dispatch(log_config.format));
+    if let Some(file) = &log_config.file {
+        let rotating_file = RotatingFile::open(file, log_config.max_file_size, log_config.max_files)?;
+        dispatch = dispatch.chain(file_dispatch(log_config.format, rotating_file));
+    }
+    dispatch.apply()?;
     Ok(())
 }
+
+fn stderr_dispatch(format: LogFormat) -> fern::Dispatch {
+    match format {
+        LogFormat::Text => {
+            let colors = fern::colors::ColoredLevelConfig::new().info(fern::colors::Color::Green)
+                                                                .warn(fern::colors::Color::Yellow)
+                                                                .error(fern::colors::Color::Red)
+                                                                .debug(fern::colors::Color::Blue)
+                                                                .trace(fern::colors::Color::Magenta);
+            fern::Dispatch::new()
+                .format(move |out, message, record| {
+                    out.finish(format_args!(
+                        "{}: {}{}",
+                        colors.color(record.level()),
+                        message,
+                        text_fields(record)
+                    ))
+                })
+                .chain(io::stderr())
+        },
+        LogFormat::Json => {
+            fern::Dispatch::new()
+                .format(|out, message, record| out.finish(format_args!("{}", json_record(message, record))))
+                .chain(io::stderr())
+        }
+    }
+}
+
+fn file_dispatch(format: LogFormat, rotating_file: RotatingFile) -> fern::Dispatch {
+    let file: Box<dyn Write + Send> = Box::new(rotating_file);
+    match format {
+        LogFormat::Text => {
+            fern::Dispatch::new()
+                .format(|out, message, record| {
+                    out.finish(format_args!(
+                        "[{}] {}: {}{}",
+                        chrono::Local::now().to_rfc3339(),
+                        record.level(),
+                        message,
+                        text_fields(record)
+                    ))
+                })
+                .chain(file)
+        },
+        LogFormat::Json => {
+            fern::Dispatch::new()
+                .format(|out, message, record| out.finish(format_args!("{}", json_record(message, record))))
+                .chain(file)
+        }
+    }
+}
+
+// Collects the structured fields of a record, e.g. log::info!(server:% = address, latency_ms = 3; "...")
+struct FieldsCollector {
+    fields: serde_json::Map<String, serde_json::Value>
+}
+
+impl<'kvs> log::kv::VisitSource<'kvs> for FieldsCollector {
+    fn visit_pair(&mut self, key: log::kv::Key<'kvs>, value: log::kv::Value<'kvs>) -> Result<(), log::kv::Error> {
+        let value = if let Some(v) = value.to_u64() {
+            serde_json::Value::from(v)
+        } else if let Some(v) = value.to_i64() {
+            serde_json::Value::from(v)
+        } else if let Some(v) = value.to_f64() {
+            serde_json::Value::from(v)
+        } else if let Some(v) = value.to_bool() {
+            serde_json::Value::from(v)
+        } else {
+            serde_json::Value::from(value.to_string())
+        };
+        self.fields.insert(key.to_string(), value);
+        Ok(())
+    }
+}
+
+fn record_fields(record: &log::Record) -> serde_json::Map<String, serde_json::Value> {
+    let mut collector = FieldsCollector { fields: serde_json::Map::new() };
+    // FieldsCollector never fails, and logging from here would re-enter the logger
+    let _ = record.key_values().visit(&mut collector);
+    collector.fields
+}
+
+fn text_fields(record: &log::Record) -> String {
+    let mut fields = String::default();
+    for (key, value) in record_fields(record) {
+        match value {
+            serde_json::Value::String(s) => fields.push_str(&format!(" {}={}", key, s)),
+            v => fields.push_str(&format!(" {}={}", key, v))
+        }
+    }
+    fields
+}
+
+fn json_record(message: &std::fmt::Arguments, record: &log::Record) -> serde_json::Value {
+    let mut json_record = record_fields(record);
+    json_record.insert(String::from("timestamp"), serde_json::Value::from(chrono::Local::now().to_rfc3339()));
+    json_record.insert(String::from("level"), serde_json::Value::from(record.level().to_string()));
+    json_record.insert(String::from("target"), serde_json::Value::from(record.target()));
+    json_record.insert(String::from("message"), serde_json::Value::from(message.to_string()));
+    serde_json::Value::Object(json_record)
+}
+
+// A log file that is renamed to `<file>.1` once it grows past `max_size` bytes, the older files are
+// shifted to `<file>.2`, `<file>.3`, ... and the ones beyond `max_files` are deleted.
+pub struct RotatingFile {
+    path: path::PathBuf,
+    file: fs::File,
+    size: u64,
+    max_size: u64,
+    max_files: usize,
+    at_line_start: bool
+}
+
+impl RotatingFile {
+    pub fn open(path: &path::Path, max_size: u64, max_files: usize) -> io::Result<RotatingFile> {
+        let file = fs::OpenOptions::new().create(true).append(true).open(path)?;
+        let size = file.metadata()?.len();
+        Ok(RotatingFile { path: path.to_path_buf(), file, size, max_size, max_files, at_line_start: true })
+    }
+
+    fn rotated_path(&self, index: usize) -> path::PathBuf {
+        let mut rotated = self.path.clone().into_os_string();
+        rotated.push(format!(".{}", index));
+        path::PathBuf::from(rotated)
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        if self.max_files == 0 {
+            self.file = fs::OpenOptions::new().write(true).truncate(true).open(&self.path)?;
+        } else {
+            let oldest = self.rotated_path(self.max_files);
+            if oldest.exists() {
+                fs::remove_file(&oldest)?;
+            }
+            for index in (1..self.max_files).rev() {
+                let rotated = self.rotated_path(index);
+                if rotated.exists() {
+                    fs::rename(&rotated, self.rotated_path(index + 1))?;
+                }
+            }
+            fs::rename(&self.path, self.rotated_path(1))?;
+            self.file = fs::OpenOptions::new().create(true).append(true).open(&self.path)?;
+        }
+        self.size = 0;
+        Ok(())
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // Only rotate between lines, a record may arrive in several writes
+        if self.at_line_start && self.size > 0 && self.size + buf.len() as u64 > self.max_size {
+            self.rotate()?;
+        }
+        let bytes_written = self.file.write(buf)?;
+        self.size += bytes_written as u64;
+        self.at_line_start = buf[..bytes_written].ends_with(b"\n");
+        Ok(bytes_written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+// The settings of a server stored in the profiles file, every setting can be overridden in the
+// command line.
+#[derive(Deserialize, Default, Clone, Debug)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Profile {
+    pub server_address: Option<String>,
+    pub admin_password: Option<String>,
+    // Seconds
+    pub connect_timeout: Option<f64>,
+    // Seconds, applies to every read and write
+    pub timeout: Option<f64>,
+    pub retries: Option<u32>,
+    pub tls: Option<bool>,
+    pub tls_ca_file: Option<String>,
+    pub tls_client_cert: Option<String>,
+    pub tls_client_key: Option<String>,
+    // SHA-256 of the server certificate, AB:CD:...
+    pub tls_fingerprint: Option<String>,
+    pub tls_server_name: Option<String>,
+    // socks5://[USER:PASSWORD@]HOST:PORT or http://[USER:PASSWORD@]HOST:PORT
+    pub proxy: Option<String>,
+    // password or challenge
+    pub auth: Option<String>,
+    pub sign: Option<bool>,
+    // The groups of servers this one belongs to, e.g. config-diff --group prod
+    pub groups: Option<Vec<String>>
+}
+
+impl Profile {
+    // Loads the profile selected with --profile from the file given with --config, the
+    // ADMINT_CONFIG variable or the default path. Without --profile there is nothing to load.
+    pub fn from_clap_matches(matches: &clap::ArgMatches) -> Result<Option<Profile>, String> {
+        if let Some(name) = global_value_of(matches, "profile") {
+            let profiles = Profiles::from_clap_matches(matches)?;
+            if let Some(profile) = profiles.get(name) {
+                return Ok(Some(profile.clone()));
+            }
+            return Err(format!("There is no profile named {}", name));
+        }
+        Ok(None)
+    }
+}
+
+// The file where the profiles are stored, e.g.:
+// [profiles.mint-1]
+// server-address = "192.168.1.70:7000"
+// admin-password = "secret"
+// connect-timeout = 3
+// timeout = 10
+// retries = 2
+// tls = true
+// tls-ca-file = "/etc/admint/mint-ca.pem"
+// groups = ["prod"]
+#[derive(Deserialize, Default, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Profiles {
+    #[serde(default)]
+    pub profiles: collections::BTreeMap<String, Profile>
+}
+
+impl Profiles {
+    pub fn load(path: &path::Path) -> Result<Profiles, String> {
+        match fs::read_to_string(path) {
+            Ok(contents) => Profiles::from_toml_str(&contents).map_err(|e| format!("{}: {}", path.display(), e)),
+            Err(e) => Err(format!("Could not read {}: {}", path.display(), e))
+        }
+    }
+
+    // A missing default file just means that there are no profiles.
+    pub fn load_default() -> Result<Profiles, String> {
+        if let Some(path) = default_profiles_path() {
+            if path.exists() {
+                return Profiles::load(&path);
+            }
+        }
+        Ok(Profiles::default())
+    }
+
+    pub fn from_toml_str(contents: &str) -> Result<Profiles, String> {
+        toml::from_str::<Profiles>(contents).map_err(|e| e.to_string())
+    }
+
+    // The file given with --config, the ADMINT_CONFIG variable or the default path
+    pub fn from_clap_matches(matches: &clap::ArgMatches) -> Result<Profiles, String> {
+        match global_value_of(matches, "config") {
+            Some(path) => Profiles::load(path::Path::new(path)),
+            None => Profiles::load_default()
+        }
+    }
+
+    pub fn path_from_clap_matches(matches: &clap::ArgMatches) -> Option<path::PathBuf> {
+        match global_value_of(matches, "config") {
+            Some(path) => Some(path::PathBuf::from(path)),
+            None => default_profiles_path()
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Profile> {
+        self.profiles.get(name)
+    }
+
+    // Sorted by name
+    pub fn in_group(&self, group: &str) -> Vec<(&String, &Profile)> {
+        self.profiles.iter()
+            .filter(|(_name, profile)| profile.groups.iter().flatten().any(|g| g == group))
+            .collect()
+    }
+}
+
+// Replaces the admin-password of a profile in the file, the rest of the file is kept as it is,
+// comments included. The new file is written next to the old one and renamed over it, so the file
+// always has either the old or the new password.
+pub fn set_admin_password(path: &path::Path, name: &str, admin_password: &str) -> Result<(), String> {
+    let contents = fs::read_to_string(path).map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+    let line = format!("admin-password = {}", toml::Value::String(admin_password.to_string()));
+    let headers = [format!("[profiles.{}]", name), format!("[profiles.\"{}\"]", name)];
+    let mut lines: Vec<String> = contents.lines().map(String::from).collect();
+    let header = lines.iter().position(|l| headers.iter().any(|header| l.trim() == header))
+        .ok_or(format!("There is no [profiles.{}] table in {}", name, path.display()))?;
+    let end = lines.iter().skip(header + 1).position(|l| l.trim_start().starts_with('['))
+        .map_or(lines.len(), |i| header + 1 + i);
+    let existing = (header + 1..end).find(|&i| {
+        let l = lines[i].trim_start();
+        l.starts_with("admin-password") && l["admin-password".len()..].trim_start().starts_with('=')
+    });
+    match existing {
+        Some(i) => lines[i] = line,
+        None => lines.insert(header + 1, line)
+    }
+    let mut updated = lines.join("\n");
+    if contents.ends_with('\n') {
+        updated.push('\n');
+    }
+    // Never write a file that does not have the new password
+    let profiles = Profiles::from_toml_str(&updated).map_err(|e| format!("Could not update {}: {}", path.display(), e))?;
+    if profiles.get(name).and_then(|profile| profile.admin_password.as_deref()) != Some(admin_password) {
+        return Err(format!("Could not update the admin-password of {} in {}", name, path.display()));
+    }
+    let temporary = path.with_file_name(format!(".{}.tmp", path.file_name().and_then(|n| n.to_str()).unwrap_or("profiles.toml")));
+    let write = || -> io::Result<()> {
+        let mut file = fs::File::create(&temporary)?;
+        file.set_permissions(fs::metadata(path)?.permissions())?;
+        file.write_all(updated.as_bytes())?;
+        file.sync_all()?;
+        fs::rename(&temporary, path)
+    };
+    write().map_err(|e| {
+        let _ = fs::remove_file(&temporary);
+        format!("Could not write {}: {}", path.display(), e)
+    })
+}
+
+// $ADMINT_CONFIG or $HOME/.config/admint/profiles.toml
+pub fn default_profiles_path() -> Option<path::PathBuf> {
+    if let Some(path) = env::var_os(PROFILES_PATH_VAR) {
+        return Some(path::PathBuf::from(path));
+    }
+    env::var_os("HOME").map(|home| path::Path::new(&home).join(".config").join("admint").join("profiles.toml"))
+}
+
+// Global args can be given before or after the subcommand name, the ones given after it are only
+// stored in the subcommand matches.
+pub fn global_value_of<'a>(matches: &'a clap::ArgMatches, name: &str) -> Option<&'a str> {
+    if let (_name, Some(subcommand_matches)) = matches.subcommand() {
+        if let Some(value) = subcommand_matches.value_of(name) {
+            return Some(value);
+        }
+    }
+    matches.value_of(name)
+}
+
+pub fn global_is_present(matches: &clap::ArgMatches, name: &str) -> bool {
+    if let (_name, Some(subcommand_matches)) = matches.subcommand() {
+        if subcommand_matches.is_present(name) {
+            return true;
+        }
+    }
+    matches.is_present(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temporary_dir(name: &str) -> path::PathBuf {
+        let dir = env::temp_dir().join(format!("admint-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn verbosity_levels() {
+        assert_eq!(level_from_verbosity(0, 0), log::LevelFilter::Warn);
+        assert_eq!(level_from_verbosity(1, 0), log::LevelFilter::Info);
+        assert_eq!(level_from_verbosity(2, 0), log::LevelFilter::Debug);
+        assert_eq!(level_from_verbosity(5, 0), log::LevelFilter::Trace);
+        assert_eq!(level_from_verbosity(0, 1), log::LevelFilter::Error);
+        assert_eq!(level_from_verbosity(0, 2), log::LevelFilter::Off);
+    }
+
+    #[test]
+    fn log_flags() {
+        let matches = crate::cli::build_app()
+            .get_matches_from_safe(vec!["admint", "-vv", "--log-format", "json", "--log-max-files", "2", "running-config", "--log-max-size", "10", "key", "127.0.0.1:7000"])
+            .unwrap();
+        let log_config = LogConfig::from_clap_matches(&matches).unwrap();
+        assert_eq!(log_config.level, log::LevelFilter::Debug);
+        assert_eq!(log_config.format, LogFormat::Json);
+        assert_eq!((log_config.max_file_size, log_config.max_files), (10, 2));
+    }
+
+    #[test]
+    fn rotating_file_keeps_max_files() {
+        let dir = temporary_dir("rotating");
+        let path = dir.join("admint.log");
+        let mut file = RotatingFile::open(&path, 10, 2).unwrap();
+        for line in &["first line\n", "second line\n", "third line\n", "fourth line\n"] {
+            file.write_all(line.as_bytes()).unwrap();
+        }
+        file.flush().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "fourth line\n");
+        assert_eq!(fs::read_to_string(dir.join("admint.log.1")).unwrap(), "third line\n");
+        assert_eq!(fs::read_to_string(dir.join("admint.log.2")).unwrap(), "second line\n");
+        assert!(!dir.join("admint.log.3").exists());
+    }
+
+    #[test]
+    fn rotating_file_rotates_between_lines() {
+        let dir = temporary_dir("rotating-lines");
+        let path = dir.join("admint.log");
+        let mut file = RotatingFile::open(&path, 8, 1).unwrap();
+        file.write_all(b"a record ").unwrap();
+        file.write_all(b"in two writes\n").unwrap();
+        file.flush().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "a record in two writes\n");
+        assert!(!dir.join("admint.log.1").exists());
+    }
+
+    #[test]
+    fn json_records_have_the_fields() {
+        let key_values = [("server", "127.0.0.1:7000")];
+        let record = log::Record::builder()
+            .args(format_args!("Request sent"))
+            .level(log::Level::Info)
+            .target("admint::commands")
+            .key_values(&key_values)
+            .build();
+        let json = json_record(record.args(), &record);
+        assert_eq!(json["message"], "Request sent");
+        assert_eq!(json["level"], "INFO");
+        assert_eq!(json["target"], "admint::commands");
+        assert_eq!(json["server"], "127.0.0.1:7000");
+        assert!(chrono::DateTime::parse_from_rfc3339(json["timestamp"].as_str().unwrap()).is_ok());
+        assert_eq!(text_fields(&record), " server=127.0.0.1:7000");
+    }
+}
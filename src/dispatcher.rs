@@ -0,0 +1,338 @@
+// Author: Jorge Alarcon Alvarez
+// Email:  jorge4larcon@gmail.com
+// This module is used to answer the admin requests on the server side: it checks the admin
+// password, applies the request to the clients and the configuration and builds the reply, so any
+// MINT server written in Rust can embed the admin support.
+
+use crate::auth;
+use crate::clients;
+use crate::ipparser;
+use crate::server_config::ServerConfig;
+use std::convert::TryFrom;
+
+// The error codes of the replies {"error":CODE,"name":NAME}
+pub const BAD_REQUEST: u64 = 1;
+pub const WRONG_PASSWORD: u64 = 2;
+pub const UNKNOWN_METHOD: u64 = 3;
+pub const INVALID_VALUE: u64 = 4;
+pub const NOT_FOUND: u64 = 5;
+pub const NOT_SIGNED: u64 = 6;
+
+// One per server, the challenges are per connection and the server keeps them
+#[derive(Default)]
+pub struct Dispatcher {
+    // Refuse the requests that are not signed
+    pub require_signatures: bool,
+    replay_guard: auth::ReplayGuard
+}
+
+impl Dispatcher {
+    pub fn new(require_signatures: bool) -> Dispatcher {
+        Dispatcher { require_signatures, replay_guard: auth::ReplayGuard::default() }
+    }
+
+    // The reply to a request, the one inside a signed challenge answer included
+    pub fn dispatch(&mut self, request: &serde_json::Value, challenge: &mut auth::Challenge,
+                    clients: &mut clients::ClientsMap, config: &mut ServerConfig) -> String {
+        if request.get("user").and_then(|user| user.as_str()) != Some("admin") {
+            return error_reply(BAD_REQUEST, "only the admin user can send requests");
+        }
+        if auth::Challenge::is_challenge_request(request) {
+            return challenge.issue();
+        }
+        let request = if auth::Challenge::is_signed_request(request) {
+            match challenge.open(config.key(), request) {
+                Some(request) => request,
+                None => return error_reply(WRONG_PASSWORD, "the answer to the challenge is not valid")
+            }
+        } else if request.get("password").and_then(|password| password.as_str()).is_some_and(|password| auth::verify_password(config.key(), password)) {
+            request.clone()
+        } else {
+            return error_reply(WRONG_PASSWORD, "wrong admin password");
+        };
+        if auth::ReplayGuard::is_signed(&request) {
+            if let Err(e) = self.replay_guard.verify(config.key(), &request) {
+                return error_reply(NOT_SIGNED, &e.to_string());
+            }
+        } else if self.require_signatures {
+            return error_reply(NOT_SIGNED, &auth::SignatureError::Missing.to_string());
+        }
+        match request.get("method").and_then(|method| method.as_str()) {
+            Some("get") => get(&request, clients, config),
+            Some("set") => set(&request, clients, config),
+            Some("drop") => drop(&request, clients),
+            _ => error_reply(UNKNOWN_METHOD, "unknown method")
+        }
+    }
+}
+
+fn get(request: &serde_json::Value, clients: &clients::ClientsMap, config: &ServerConfig) -> String {
+    match request.get("how").and_then(|how| how.as_str()) {
+        Some("running_configuration") => {
+            serde_json::json!({ "result": "ok", "running_config": config.running_config() }).to_string()
+        },
+        Some("index") => {
+            match (u64_field(request, "start_index"), u64_field(request, "end_index")) {
+                (Some(start_index), Some(end_index)) => {
+                    let clients: Vec<serde_json::Value> = clients.range(start_index as usize, end_index as usize).iter()
+                        .map(|(mac, client)| client_to_json(mac, client))
+                        .collect();
+                    serde_json::json!({ "result": "ok", "clients": clients }).to_string()
+                },
+                _ => error_reply(BAD_REQUEST, "start_index and end_index must be numbers")
+            }
+        },
+        Some("mac") => {
+            match request.get("mac").and_then(|mac| mac.as_str()).and_then(ipparser::MacAddress::new_from_str) {
+                Some(mac) => match clients.get_by_mac(&mac) {
+                    Some(client) => serde_json::json!({ "result": "ok", "client": client_to_json(&mac, &client) }).to_string(),
+                    None => error_reply(NOT_FOUND, "there is no client with that mac")
+                },
+                None => error_reply(BAD_REQUEST, "mac must be a MAC address")
+            }
+        },
+        Some("username") => {
+            match (request.get("username").and_then(|username| username.as_str()), u64_field(request, "start_index")) {
+                (Some(pattern), Some(start_index)) => {
+                    let (clients, end_index) = clients.usernames_that_contain_with_macs(start_index as usize, config.list_size() as usize, pattern);
+                    let clients: Vec<serde_json::Value> = clients.iter().map(|(mac, client)| client_to_json(mac, client)).collect();
+                    serde_json::json!({ "result": "ok", "clients": clients, "end_index": end_index }).to_string()
+                },
+                _ => error_reply(BAD_REQUEST, "username must be a string and start_index a number")
+            }
+        },
+        _ => error_reply(UNKNOWN_METHOD, "unknown get")
+    }
+}
+
+fn set(request: &serde_json::Value, clients: &mut clients::ClientsMap, config: &mut ServerConfig) -> String {
+    match request.get("what").and_then(|what| what.as_str()) {
+        Some("capacity") => {
+            match u16_field(request, "capacity").map(|capacity| config.set_capacity(capacity)) {
+                Some(Ok(())) => {},
+                _ => return error_reply(INVALID_VALUE, "capacity must be between [2,65535]")
+            }
+        },
+        Some("list_size") => {
+            match u16_field(request, "list_size") {
+                Some(list_size) => config.set_list_size(list_size),
+                None => return error_reply(INVALID_VALUE, "list_size must be between [0,65535]")
+            }
+        },
+        Some("drop_votes") => {
+            match u8_field(request, "drop_votes").map(|drop_votes| config.set_drop_votes(drop_votes)) {
+                Some(Ok(())) => {
+                    let dropped_clients: Vec<serde_json::Value> = clients.drop_amount(config.drop_votes()).iter()
+                        .map(|(mac, client)| client_to_json(mac, client))
+                        .collect();
+                    return serde_json::json!({ "result": "ok", "dropped_clients": dropped_clients }).to_string();
+                },
+                _ => return error_reply(INVALID_VALUE, "drop_votes must be between [1,255]")
+            }
+        },
+        Some("drop_verification") => {
+            match request.get("drop_verification").and_then(|dv| dv.as_bool()) {
+                Some(drop_verification) => config.set_drop_verification(drop_verification),
+                None => return error_reply(INVALID_VALUE, "drop_verification must be a boolean")
+            }
+        },
+        Some("key") => {
+            match str_field(request, "key").map(|key| config.set_key(key)) {
+                Some(Ok(())) => {},
+                _ => return error_reply(INVALID_VALUE, "key must have less than 33 printable ascii characters")
+            }
+        },
+        Some("password") => {
+            match str_field(request, "new_password").map(|password| config.set_password(password)) {
+                Some(Ok(())) => {},
+                _ => return error_reply(INVALID_VALUE, "new_password must have less than 33 printable ascii characters")
+            }
+        },
+        _ => return error_reply(UNKNOWN_METHOD, "unknown set")
+    }
+    ok_reply()
+}
+
+fn drop(request: &serde_json::Value, clients: &mut clients::ClientsMap) -> String {
+    match request.get("ip").and_then(|ip| ip.as_str()).and_then(ipparser::ipv4_to_u32) {
+        Some(ip) => {
+            if clients.drop_by_ipv4(ip) {
+                ok_reply()
+            } else {
+                error_reply(NOT_FOUND, "there is no client with that ip")
+            }
+        },
+        None => error_reply(BAD_REQUEST, "ip must be an IPv4 address")
+    }
+}
+
+fn u64_field(request: &serde_json::Value, field: &str) -> Option<u64> {
+    request.get(field).and_then(|value| value.as_u64())
+}
+
+fn u16_field(request: &serde_json::Value, field: &str) -> Option<u16> {
+    u64_field(request, field).and_then(|value| u16::try_from(value).ok())
+}
+
+fn u8_field(request: &serde_json::Value, field: &str) -> Option<u8> {
+    u64_field(request, field).and_then(|value| u8::try_from(value).ok())
+}
+
+fn str_field<'a>(request: &'a serde_json::Value, field: &str) -> Option<&'a str> {
+    request.get(field).and_then(|value| value.as_str())
+}
+
+fn client_to_json(mac: &ipparser::MacAddress, client: &clients::Client) -> serde_json::Value {
+    serde_json::from_str(&client.to_json_string_with_mac(mac)).unwrap_or(serde_json::Value::Null)
+}
+
+pub fn ok_reply() -> String {
+    serde_json::json!({ "result": "ok" }).to_string()
+}
+
+pub fn error_reply(code: u64, name: &str) -> String {
+    serde_json::json!({ "error": code, "name": name }).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAC: &str = "0200.0000.0001";
+
+    struct Server {
+        dispatcher: Dispatcher,
+        challenge: auth::Challenge,
+        clients: clients::ClientsMap,
+        config: ServerConfig
+    }
+
+    impl Server {
+        // Two clients, 10.0.0.1 "quiet_fox" with 2 drop votes and 10.0.0.2 "brave-otter" with none
+        fn new(require_signatures: bool) -> Server {
+            let mut clients = clients::ClientsMap::new();
+            let fox = clients::Client::new(ipparser::ipv4_to_u32("10.0.0.1").unwrap(), 7001, "quiet_fox", false, 2).unwrap();
+            let otter = clients::Client::new(ipparser::ipv4_to_u32("10.0.0.2").unwrap(), 7002, "brave-otter", false, 0).unwrap();
+            clients.insert(&ipparser::MacAddress::new_from_str(MAC).unwrap(), &fox);
+            clients.insert(&ipparser::MacAddress::new_from_str("0200.0000.0002").unwrap(), &otter);
+            Server { dispatcher: Dispatcher::new(require_signatures), challenge: auth::Challenge::new(), clients, config: ServerConfig::default() }
+        }
+
+        // Adds the user and the admin password of the default config
+        fn dispatch(&mut self, request: serde_json::Value) -> serde_json::Value {
+            let mut request = request;
+            request["user"] = serde_json::Value::from("admin");
+            request["password"] = serde_json::Value::from("admin");
+            self.dispatch_as_is(&request)
+        }
+
+        fn dispatch_as_is(&mut self, request: &serde_json::Value) -> serde_json::Value {
+            let reply = self.dispatcher.dispatch(request, &mut self.challenge, &mut self.clients, &mut self.config);
+            serde_json::from_str(&reply).unwrap()
+        }
+    }
+
+    fn error(code: u64) -> impl Fn(&serde_json::Value) -> bool {
+        move |reply| reply["error"] == code
+    }
+
+    #[test]
+    fn admin_password() {
+        let mut server = Server::new(false);
+        let request = serde_json::json!({ "user": "admin", "password": "admin", "method": "get", "how": "running_configuration" });
+        assert_eq!(server.dispatch_as_is(&request)["result"], "ok");
+        for password in &["Admin", "admin ", ""] {
+            let request = serde_json::json!({ "user": "admin", "password": password, "method": "get", "how": "running_configuration" });
+            assert!(error(WRONG_PASSWORD)(&server.dispatch_as_is(&request)), "{}", password);
+        }
+        let request = serde_json::json!({ "user": "admin", "method": "get", "how": "running_configuration" });
+        assert!(error(WRONG_PASSWORD)(&server.dispatch_as_is(&request)));
+        let request = serde_json::json!({ "user": "client", "password": "admin", "method": "get", "how": "running_configuration" });
+        assert!(error(BAD_REQUEST)(&server.dispatch_as_is(&request)));
+    }
+
+    #[test]
+    fn unknown_methods() {
+        let mut server = Server::new(false);
+        assert!(error(UNKNOWN_METHOD)(&server.dispatch(serde_json::json!({ "method": "reboot" }))));
+        assert!(error(UNKNOWN_METHOD)(&server.dispatch(serde_json::json!({}))));
+        assert!(error(UNKNOWN_METHOD)(&server.dispatch(serde_json::json!({ "method": "get", "how": "ipv6" }))));
+        assert!(error(UNKNOWN_METHOD)(&server.dispatch(serde_json::json!({ "method": "set", "what": "colour" }))));
+    }
+
+    #[test]
+    fn get_by_each_key() {
+        let mut server = Server::new(false);
+        let reply = server.dispatch(serde_json::json!({ "method": "get", "how": "index", "start_index": 0, "end_index": 10 }));
+        assert_eq!(reply["clients"].as_array().unwrap().len(), 2);
+        let reply = server.dispatch(serde_json::json!({ "method": "get", "how": "index", "start_index": 1, "end_index": 2 }));
+        assert_eq!(reply["clients"][0]["username"], "brave-otter");
+        let reply = server.dispatch(serde_json::json!({ "method": "get", "how": "mac", "mac": MAC }));
+        assert_eq!(reply["client"]["username"], "quiet_fox");
+        assert!(error(NOT_FOUND)(&server.dispatch(serde_json::json!({ "method": "get", "how": "mac", "mac": "0200.0000.0009" }))));
+        assert!(error(BAD_REQUEST)(&server.dispatch(serde_json::json!({ "method": "get", "how": "mac", "mac": "not a mac" }))));
+        let reply = server.dispatch(serde_json::json!({ "method": "get", "how": "username", "username": "OTTER", "start_index": 0 }));
+        assert_eq!(reply["clients"][0]["username"], "brave-otter");
+        assert_eq!(reply["clients"].as_array().unwrap().len(), 1);
+        assert!(error(BAD_REQUEST)(&server.dispatch(serde_json::json!({ "method": "get", "how": "username", "username": "fox" }))));
+    }
+
+    #[test]
+    fn bad_indexes() {
+        let mut server = Server::new(false);
+        for (start_index, end_index) in &[(serde_json::json!("0"), serde_json::json!(10)), (serde_json::json!(0), serde_json::json!(-1)),
+                                         (serde_json::json!(null), serde_json::json!(10)), (serde_json::json!(0.5), serde_json::json!(10))] {
+            let request = serde_json::json!({ "method": "get", "how": "index", "start_index": start_index, "end_index": end_index });
+            assert!(error(BAD_REQUEST)(&server.dispatch(request)), "{} {}", start_index, end_index);
+        }
+        let reply = server.dispatch(serde_json::json!({ "method": "get", "how": "index", "start_index": 5, "end_index": 1 }));
+        assert_eq!(reply["clients"], serde_json::json!([]));
+    }
+
+    #[test]
+    fn sets() {
+        let mut server = Server::new(false);
+        assert_eq!(server.dispatch(serde_json::json!({ "method": "set", "what": "capacity", "capacity": 50 }))["result"], "ok");
+        assert_eq!(server.config.capacity(), 50);
+        assert!(error(INVALID_VALUE)(&server.dispatch(serde_json::json!({ "method": "set", "what": "capacity", "capacity": 1 }))));
+        assert!(error(INVALID_VALUE)(&server.dispatch(serde_json::json!({ "method": "set", "what": "list_size", "list_size": 70000 }))));
+        assert!(error(INVALID_VALUE)(&server.dispatch(serde_json::json!({ "method": "set", "what": "drop_verification", "drop_verification": "no" }))));
+        assert!(error(INVALID_VALUE)(&server.dispatch(serde_json::json!({ "method": "set", "what": "password", "new_password": "a\nb" }))));
+        assert_eq!(server.config.capacity(), 50);
+        let reply = server.dispatch(serde_json::json!({ "method": "set", "what": "drop_votes", "drop_votes": 2 }));
+        assert_eq!(reply["dropped_clients"][0]["username"], "quiet_fox");
+        assert_eq!(server.clients.len(), 1);
+        assert_eq!(server.dispatch(serde_json::json!({ "method": "set", "what": "key", "key": "other" }))["result"], "ok");
+        assert!(error(WRONG_PASSWORD)(&server.dispatch(serde_json::json!({ "method": "get", "how": "running_configuration" }))));
+    }
+
+    #[test]
+    fn drops() {
+        let mut server = Server::new(false);
+        assert_eq!(server.dispatch(serde_json::json!({ "method": "drop", "ip": "10.0.0.1" }))["result"], "ok");
+        assert_eq!(server.clients.len(), 1);
+        assert!(error(NOT_FOUND)(&server.dispatch(serde_json::json!({ "method": "drop", "ip": "10.0.0.1" }))));
+        assert!(error(BAD_REQUEST)(&server.dispatch(serde_json::json!({ "method": "drop", "ip": "10.0.0" }))));
+        assert!(error(BAD_REQUEST)(&server.dispatch(serde_json::json!({ "method": "drop", "mac": MAC }))));
+        assert_eq!(server.clients.len(), 1);
+    }
+
+    #[test]
+    fn required_signatures() {
+        let mut server = Server::new(true);
+        assert!(error(NOT_SIGNED)(&server.dispatch(serde_json::json!({ "method": "get", "how": "running_configuration" }))));
+        let mut request = serde_json::Map::new();
+        request.insert(String::from("user"), serde_json::Value::from("admin"));
+        request.insert(String::from("method"), serde_json::Value::from("get"));
+        request.insert(String::from("how"), serde_json::Value::from("running_configuration"));
+        auth::sign_request(&mut request, "admin");
+        let request = serde_json::Value::Object(request);
+        assert_eq!(server.dispatch(request.clone())["result"], "ok");
+        // The same nonce again is a replay
+        assert!(error(NOT_SIGNED)(&server.dispatch(request.clone())));
+        let mut tampered = request;
+        tampered["how"] = serde_json::Value::from("index");
+        assert!(error(NOT_SIGNED)(&server.dispatch(tampered)));
+        assert_eq!(Server::new(false).dispatch(serde_json::json!({ "method": "get", "how": "running_configuration" }))["result"], "ok");
+    }
+}
@@ -0,0 +1,182 @@
+// Author: Jorge Alarcon Alvarez
+// Email:  jorge4larcon@gmail.com
+// This module is used to compare the running configs of many servers, with each other or with a
+// baseline. The secrets are never compared nor shown.
+
+extern crate clap;
+
+use crate::server_config;
+use crate::targets;
+use std::path;
+
+// Returned by config-diff: no drift, drift, the servers could not be compared
+pub const NO_DRIFT: i32 = 0;
+pub const DRIFT: i32 = 1;
+pub const DIFF_ERROR: i32 = 2;
+
+pub struct Diff {
+    // The first column is the baseline when there is one
+    pub columns: Vec<String>,
+    pub rows: Vec<DiffRow>,
+    pub servers: usize
+}
+
+pub struct DiffRow {
+    pub setting: server_config::Setting,
+    // One per column, None when the baseline does not have the setting
+    pub values: Vec<Option<serde_json::Value>>,
+    pub drifted: bool
+}
+
+impl Diff {
+    // With a baseline only its settings are compared, every server against it. Without one every
+    // non-secret setting is compared across the servers.
+    pub fn new(baseline: Option<&server_config::DesiredConfig>, configs: &[(String, server_config::ServerConfig)]) -> Diff {
+        let mut columns: Vec<String> = Vec::new();
+        let mut desired: Vec<server_config::DesiredConfig> = Vec::new();
+        if let Some(baseline) = baseline {
+            columns.push(String::from("baseline"));
+            desired.push(baseline.clone());
+        }
+        for (name, config) in configs {
+            columns.push(name.clone());
+            desired.push(server_config::DesiredConfig::from_server_config(config));
+        }
+        let settings = match baseline {
+            Some(baseline) => baseline.settings(),
+            None => server_config::Setting::ALL.iter().copied().filter(|setting| !setting.is_secret()).collect()
+        };
+        let rows = settings.into_iter().map(|setting| {
+            let values: Vec<Option<serde_json::Value>> = desired.iter().map(|d| d.get(setting)).collect();
+            let drifted = values.windows(2).any(|pair| pair[0] != pair[1]);
+            DiffRow { setting, values, drifted }
+        }).collect();
+        Diff { columns, rows, servers: configs.len() }
+    }
+
+    pub fn has_drift(&self) -> bool {
+        self.rows.iter().any(|row| row.drifted)
+    }
+
+    // A table with a row per setting, the drifted ones are marked
+    pub fn render(&self) -> String {
+        let cell = |value: &Option<serde_json::Value>| value.as_ref().map(|v| v.to_string()).unwrap_or_else(|| String::from("-"));
+        let mut widths: Vec<usize> = vec![server_config::SETTING_NAMES.iter().map(|name| name.len()).max().unwrap_or(0)];
+        for (i, column) in self.columns.iter().enumerate() {
+            let width = self.rows.iter().map(|row| cell(&row.values[i]).len()).max().unwrap_or(0);
+            widths.push(column.len().max(width));
+        }
+        let line = |cells: Vec<String>, mark: &str| {
+            let cells: Vec<String> = cells.iter().zip(&widths).map(|(cell, width)| format!("{:<width$}", cell, width = width)).collect();
+            format!("{}  {}", cells.join("  "), mark).trim_end().to_string()
+        };
+        let mut lines = vec![line(std::iter::once(String::from("SETTING")).chain(self.columns.iter().cloned()).collect(), "")];
+        for row in &self.rows {
+            let cells = std::iter::once(row.setting.name().to_string()).chain(row.values.iter().map(cell)).collect();
+            lines.push(line(cells, if row.drifted { "DRIFT" } else { "" }));
+        }
+        let drifted = self.rows.iter().filter(|row| row.drifted).count();
+        if drifted == 0 {
+            lines.push(format!("No drift across {} server(s)", self.servers));
+        } else {
+            lines.push(format!("{} setting(s) drifted", drifted));
+        }
+        lines.join("\n")
+    }
+}
+
+// Prints the differences and returns NO_DRIFT, DRIFT or DIFF_ERROR
+pub fn config_diff(matches: &clap::ArgMatches) -> i32 {
+    let subcommand_matches = match matches.subcommand_matches("config-diff") {
+        Some(subcommand_matches) => subcommand_matches,
+        None => return DIFF_ERROR
+    };
+    let baseline = match subcommand_matches.value_of("baseline") {
+        Some(baseline) => match server_config::DesiredConfig::load(path::Path::new(baseline)) {
+            Ok(baseline) => Some(baseline),
+            Err(e) => {
+                eprintln!("{}", e);
+                return DIFF_ERROR;
+            }
+        },
+        None => None
+    };
+    let targets = match targets::from_clap_matches(matches) {
+        Ok(targets) => targets,
+        Err(e) => {
+            eprintln!("{}", e);
+            return DIFF_ERROR;
+        }
+    };
+    let needed = if baseline.is_some() { 1 } else { 2 };
+    if targets.len() < needed {
+        eprintln!("config-diff needs at least {} server(s) to compare", needed);
+        return DIFF_ERROR;
+    }
+    let mut configs = Vec::new();
+    let mut failed = false;
+    for target in &targets {
+        match target.running_config() {
+            Ok(config) => configs.push((target.name.clone(), config)),
+            Err(e) => {
+                eprintln!("{}: {}", target.name, e);
+                failed = true;
+            }
+        }
+    }
+    if failed {
+        return DIFF_ERROR;
+    }
+    let diff = Diff::new(baseline.as_ref(), &configs);
+    println!("{}", diff.render());
+    if diff.has_drift() { DRIFT } else { NO_DRIFT }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn servers(configs: &[(&str, u16, &str)]) -> Vec<(String, server_config::ServerConfig)> {
+        configs.iter().map(|(name, capacity, password)| {
+            (name.to_string(), server_config::ServerConfig::new(*capacity, 20, 3, true, password, "admin").unwrap())
+        }).collect()
+    }
+
+    #[test]
+    fn only_the_settings_of_the_baseline() {
+        let baseline = server_config::DesiredConfig::from_toml_str("capacity = 150\n").unwrap();
+        let mut configs = servers(&[("a", 150, ""), ("b", 150, "")]);
+        configs[1].1.set_list_size(3);
+        let diff = Diff::new(Some(&baseline), &configs);
+        assert_eq!(diff.columns, vec!["baseline", "a", "b"]);
+        assert_eq!(diff.rows.len(), 1);
+        assert_eq!(diff.rows[0].values, vec![Some(serde_json::json!(150)); 3]);
+        assert!(!diff.has_drift());
+        configs[0].1.set_capacity(100).unwrap();
+        let diff = Diff::new(Some(&baseline), &configs);
+        assert!(diff.rows[0].drifted);
+        assert_eq!(diff.servers, 2);
+    }
+
+    #[test]
+    fn secrets_are_never_compared() {
+        let configs = servers(&[("a", 100, "first"), ("b", 100, "second")]);
+        let diff = Diff::new(None, &configs);
+        assert!(diff.rows.iter().all(|row| !row.setting.is_secret()));
+        assert_eq!(diff.rows.len(), 4);
+        assert!(!diff.has_drift());
+        let rendered = diff.render();
+        assert!(!rendered.contains("first") && !rendered.contains("second") && !rendered.contains("admin"));
+        assert!(rendered.ends_with("No drift across 2 server(s)"));
+    }
+
+    #[test]
+    fn render() {
+        let baseline = server_config::DesiredConfig::from_toml_str("capacity = 150\ndrop-verification = true\n").unwrap();
+        let diff = Diff::new(Some(&baseline), &servers(&[("a", 150, ""), ("b", 2, "")]));
+        assert_eq!(diff.render(), concat!("SETTING            baseline  a     b\n",
+                                          "capacity           150       150   2     DRIFT\n",
+                                          "drop-verification  true      true  true\n",
+                                          "1 setting(s) drifted"));
+    }
+}
@@ -2,8 +2,12 @@
 // Email:  jorge4larcon@gmail.com
 // This module is used to parse the network addresses.
 
+#![allow(clippy::result_unit_err)]
+
 use std::str::{FromStr};
-use std::net::{IpAddr, SocketAddr, SocketAddrV4, SocketAddrV6, Ipv6Addr, Ipv4Addr};
+use std::net::{IpAddr, SocketAddr, SocketAddrV4, SocketAddrV6, Ipv6Addr, Ipv4Addr, ToSocketAddrs};
+use std::io;
+use std::path;
 use regex::Regex;
 use std::fmt;
 use std::cmp;
@@ -99,9 +103,9 @@ impl AddrContainer {
 
     pub fn to_sock_addr(&self) -> SocketAddr {
         if self.is_v4() {
-            return SocketAddr::from(self.to_sock_addr4().unwrap());
+            SocketAddr::from(self.to_sock_addr4().unwrap())
         } else {
-            return SocketAddr::from(self.to_sock_addr6().unwrap());
+            SocketAddr::from(self.to_sock_addr6().unwrap())
         }
     }
 
@@ -135,27 +139,138 @@ impl AddrContainer {
     }
 }
 
+// A server address that may still need to be resolved: 192.168.1.70:7000, [fe80::1]:7000 or
+// mint-1.lab:7000
+#[derive(Clone, PartialEq, Debug)]
+pub struct HostAndPort {
+    host: String,
+    port: u16
+}
+
+impl fmt::Display for HostAndPort {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if is_ipv6_addr(&self.host) {
+            write!(f, "[{}]:{}", self.host, self.port)
+        } else {
+            write!(f, "{}:{}", self.host, self.port)
+        }
+    }
+}
+
+impl HostAndPort {
+    pub fn new_from_str(addr: &str) -> Option<HostAndPort> {
+        if let Some(sock_addr) = str_to_sockaddr(addr) {
+            return Some(HostAndPort { host: sock_addr.ip().to_string(), port: sock_addr.port() });
+        }
+        let (host, port) = addr.rsplit_once(':')?;
+        if let Ok(port) = port.parse::<u16>() {
+            if is_hostname(host) {
+                return Some(HostAndPort { host: host.to_lowercase(), port });
+            }
+        }
+        None
+    }
+
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    // Uses the system resolver, so /etc/hosts is honored. The addresses keep the resolver order.
+    pub fn resolve(&self) -> io::Result<Vec<SocketAddr>> {
+        if let Ok(ip) = IpAddr::from_str(&self.host) {
+            return Ok(vec![SocketAddr::new(ip, self.port)]);
+        }
+        Ok((self.host.as_str(), self.port).to_socket_addrs()?.collect())
+    }
+}
+
+pub const UNIX_ADDRESS_PREFIX: &str = "unix:";
+
+// Where the admin interface of a server listens: a host and port or, when the server runs in the
+// same host, a Unix socket given as unix:/run/mint/admin.sock
+#[derive(Clone, PartialEq, Debug)]
+pub enum ServerAddress {
+    Inet(HostAndPort),
+    Unix(path::PathBuf)
+}
+
+impl fmt::Display for ServerAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ServerAddress::Inet(host_and_port) => write!(f, "{}", host_and_port),
+            ServerAddress::Unix(path) => write!(f, "{}{}", UNIX_ADDRESS_PREFIX, path.display())
+        }
+    }
+}
+
+impl ServerAddress {
+    pub fn new_from_str(addr: &str) -> Option<ServerAddress> {
+        if let Some(socket_path) = addr.strip_prefix(UNIX_ADDRESS_PREFIX) {
+            if socket_path.is_empty() {
+                return None;
+            }
+            return Some(ServerAddress::Unix(path::PathBuf::from(socket_path)));
+        }
+        HostAndPort::new_from_str(addr).map(ServerAddress::Inet)
+    }
+}
+
+pub fn is_server_address(addr: &str) -> bool {
+    ServerAddress::new_from_str(addr).is_some()
+}
+
+// RFC 1123 host names: dot separated labels of letters, digits and hyphens that do not start or
+// end with a hyphen, 253 characters at most. The last label can not be just digits, that would be
+// a malformed IPv4 address like 192.168.1.300
+pub fn is_hostname(host: &str) -> bool {
+    if host.is_empty() || host.len() > 253 || !host.is_ascii() {
+        return false;
+    }
+    let labels: Vec<&str> = host.trim_end_matches('.').split('.').collect();
+    if let Some(last_label) = labels.last() {
+        if last_label.chars().all(|c| c.is_ascii_digit()) {
+            return false;
+        }
+    }
+    labels.iter().all(|label| is_hostname_label(label))
+}
+
+// 1 to 63 letters, digits and hyphens, not starting nor ending with a hyphen
+fn is_hostname_label(label: &str) -> bool {
+    !label.is_empty() && label.len() <= 63
+        && !label.starts_with('-') && !label.ends_with('-')
+        && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+}
+
+pub fn is_host_and_port(addr: &str) -> bool {
+    HostAndPort::new_from_str(addr).is_some()
+}
+
+pub fn str_to_sockaddr(addr: &str) -> Option<SocketAddr> {
+    SocketAddr::from_str(addr).ok()
+}
+
 pub fn is_socket_addr(addr: &str) -> bool {
     if addr.is_ascii() {
         if addr.contains("%") { // IPv6 case
             let addr_ifport: Vec<&str> = addr.split("%").collect();
-            if addr_ifport.len() == 2 {
-                if is_ipv6_addr(addr_ifport[0]) {
-                    let scope_port: Vec<&str> = addr_ifport[1].split(":").collect();
-                    if scope_port.len() == 2 {
-                        if let Ok(port) = scope_port[1].parse::<i32>() {
-                            return is_port_number(port);
-                        }
+            if addr_ifport.len() == 2 && is_ipv6_addr(addr_ifport[0]) {
+                let scope_port: Vec<&str> = addr_ifport[1].split(":").collect();
+                if scope_port.len() == 2 {
+                    if let Ok(port) = scope_port[1].parse::<i32>() {
+                        return is_port_number(port);
                     }
                 }
             }
         } else { // IPv4 case
             let addr_port: Vec<&str> = addr.split(":").collect();
-            if addr_port.len() == 2 {
-                if is_ipv4_addr(addr_port[0]) {
-                    if let Ok(port) = addr_port[1].parse::<i32>() {
-                        return is_port_number(port);
-                    }
+            if addr_port.len() == 2 && is_ipv4_addr(addr_port[0]) {
+                if let Ok(port) = addr_port[1].parse::<i32>() {
+                    return is_port_number(port);
                 }
             }
         }
@@ -164,21 +279,15 @@ pub fn is_socket_addr(addr: &str) -> bool {
 }
 
 pub fn is_socket_addr_v4(addr: &str) -> bool {
-    match SocketAddrV4::from_str(addr) {
-        Ok(_) => true,
-        _ => false
-    }
+    SocketAddrV4::from_str(addr).is_ok()
 }
 
 pub fn is_socket_addr_v6(addr: &str) -> bool {
-    match SocketAddrV6::from_str(addr) {
-        Ok(_) => true,
-        _ => false
-    }
+    SocketAddrV6::from_str(addr).is_ok()
 }
 
 pub fn is_port_number(p: i32) -> bool {
-    p >= 0 && p <= 65535
+    (0..=65535).contains(&p)
 }
 
 pub fn is_ip_addr(addr: &str) -> bool {
@@ -187,7 +296,7 @@ pub fn is_ip_addr(addr: &str) -> bool {
 
 // TODO: Optimize this function
 pub fn is_ipv4_addr(addr: &str) -> bool {
-    if let Ok(_) = Ipv4Addr::from_str(addr) {
+    if Ipv4Addr::from_str(addr).is_ok() {
         return true;
     }
     if addr.is_ascii() {
@@ -199,7 +308,7 @@ pub fn is_ipv4_addr(addr: &str) -> bool {
 
 // TODO: Optimize this function
 pub fn is_ipv6_addr(addr: &str) -> bool {
-    if let Ok(_) = Ipv6Addr::from_str(addr) {
+    if Ipv6Addr::from_str(addr).is_ok() {
         return true;
     }
     if addr.is_ascii() {
@@ -210,17 +319,11 @@ pub fn is_ipv6_addr(addr: &str) -> bool {
 }
 
 pub fn str_to_ipv4addr(addr: &str) -> Option<Ipv4Addr> {
-    match Ipv4Addr::from_str(addr) {
-        Ok(ipv4) => Some(ipv4),
-        _ => None
-    }
+    Ipv4Addr::from_str(addr).ok()
 }
 
 pub fn str_to_ipv6addr(addr: &str) -> Option<Ipv6Addr> {
-    match Ipv6Addr::from_str(addr) {
-        Ok(ipv6) => Some(ipv6),
-        _ => None
-    }
+    Ipv6Addr::from_str(addr).ok()
 }
 
 pub fn parse_ip(addr: &str) -> Result<IpAddr, ()> {
@@ -253,7 +356,7 @@ pub fn mac_to_u64(mac: &str) -> Option<u64> {
         };
         return Some(u64::from_str_radix(m.as_str(), 16).unwrap());
     }
-    return None;
+    None
 }
 
 // Only 3 separators: `.`, `:` and `-`
@@ -275,7 +378,7 @@ pub fn u64_to_mac(mac: u64, sep: char) -> Option<String> {
 //     let other_mac = mac_to_u64(other_mac)?;
 //     if mac < other_mac {
 //         return Ok(-1);
-//     } else if other_mac > mac {
+//     } else if mac > other_mac {
 //         return Ok(1);
 //     }
 //     Ok(0)
@@ -285,7 +388,7 @@ pub fn mac_compare_u64(mac: u64, other_mac: u64) -> Result<i8, ()> {
     if is_mac_u64(mac) && is_mac_u64(other_mac) {
         if mac < other_mac {
             return Ok(-1);
-        } else if other_mac > mac {
+        } else if mac > other_mac {
             return Ok(1);
         }
         return Ok(0);
@@ -314,19 +417,11 @@ pub fn ipv6_compare_u128(ipv6: u128, other_ipv6: u128) -> i8 {
 }
 
 pub fn sockaddrv4str_to_sockaddrv4(sockaddrv4: &str) -> Option<SocketAddrV4> {
-    if let Ok(sockaddr) = SocketAddrV4::from_str(sockaddrv4) {
-        Some(sockaddr)
-    } else {
-        None
-    }
+    SocketAddrV4::from_str(sockaddrv4).ok()
 }
 
 pub fn sockaddrv6str_to_ipv6sockaddr(sockaddrv6: &str) -> Option<SocketAddrV6> {
-    if let Ok(sockaddr) = SocketAddrV6::from_str(sockaddrv6) {
-        Some(sockaddr)
-    } else {
-        None
-    }
+    SocketAddrV6::from_str(sockaddrv6).ok()
 }
 
 // pub fn ipv4_compare_str(ipv4: &str, other_ipv4: &str) -> Result<i8, ()> {
@@ -334,7 +429,7 @@ pub fn sockaddrv6str_to_ipv6sockaddr(sockaddrv6: &str) -> Option<SocketAddrV6> {
 //     let other_ipv4 = ipv4_to_u32(other_ipv4)?;
 //     if ipv4 < other_ipv4 {
 //         return Ok(-1);
-//     } else if other_ipv4 > ipv4 {
+//     } else if ipv4 > other_ipv4 {
 //         return Ok(1);
 //     }
 //     Ok(0)
@@ -343,14 +438,14 @@ pub fn sockaddrv6str_to_ipv6sockaddr(sockaddrv6: &str) -> Option<SocketAddrV6> {
 pub fn ipv4_compare_u32(ipv4: u32, other_ipv4: u32) -> i8 {
     if ipv4 < other_ipv4 {
         return -1;
-    } else if other_ipv4 > ipv4 {
+    } else if ipv4 > other_ipv4 {
         return 1;
     }
     0
 }
 
 pub fn is_mac_u64(mac: u64) -> bool {
-    return mac <= 281474976710655;
+    mac <= 281474976710655
 }
 
 pub fn ipv6_to_u128(ipv6: &str) -> Option<u128> {
@@ -418,7 +513,7 @@ pub fn ipv4addr_to_u32(ipv4addr: &Ipv4Addr) -> u32 {
                             65536   *u32::from(o[1]) +
                             256     *u32::from(o[2]) +
                                     u32::from(o[3]);
-    return ip_number;
+    ip_number
 }
 
 pub fn ipv6addr_to_u128(ipv6addr: &Ipv6Addr) -> u128 {
@@ -426,15 +521,14 @@ pub fn ipv6addr_to_u128(ipv6addr: &Ipv6Addr) -> u128 {
     // IP Number = (65536^7)*a + (65536^6)*b + (65536^5)*c + (65536^4)*d + (65536^3)*e + (65536^2)*f + 65536*g + h
     // where IP Address = a:b:c:d:e:f:g:h
     let n: u128 = 65536;
-    let ip_number = (n.pow(7))*u128::from(h[0]) +
-                    (n.pow(6))*u128::from(h[1]) +
-                    (n.pow(5))*u128::from(h[2]) +
-                    (n.pow(4))*u128::from(h[3]) +
-                    (n.pow(3))*u128::from(h[4]) +
-                    (n.pow(2))*u128::from(h[5]) +
-                    (n)       *u128::from(h[6]) +
-                                u128::from(h[7]);
-    return ip_number;
+    (n.pow(7))*u128::from(h[0]) +
+    (n.pow(6))*u128::from(h[1]) +
+    (n.pow(5))*u128::from(h[2]) +
+    (n.pow(4))*u128::from(h[3]) +
+    (n.pow(3))*u128::from(h[4]) +
+    (n.pow(2))*u128::from(h[5]) +
+    (n)       *u128::from(h[6]) +
+                u128::from(h[7])
 }
 
 pub fn ipv6addr_str_to_u128(ipaddr: &IpAddr) -> Option<u128> {    
@@ -450,3 +544,57 @@ pub fn ipv4addr_str_to_u32(ipaddr: &IpAddr) -> Option<u32> {
     }
     None
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The greater-than case used to compare the operands the other way around and returned 0,
+    // like ipv6_compare_u128 they return 1 now
+    #[test]
+    fn compare_functions() {
+        assert_eq!(ipv4_compare_u32(1, 2), -1);
+        assert_eq!(ipv4_compare_u32(2, 1), 1);
+        assert_eq!(ipv4_compare_u32(2, 2), 0);
+        assert_eq!(mac_compare_u64(1, 2), Ok(-1));
+        assert_eq!(mac_compare_u64(2, 1), Ok(1));
+        assert_eq!(mac_compare_u64(2, 2), Ok(0));
+        assert_eq!(mac_compare_u64(1 << 48, 1), Err(()));
+        assert_eq!(ipv6_compare_u128(2, 1), 1);
+    }
+
+    #[test]
+    fn hostnames() {
+        for host in &["localhost", "mint-1.lab", "MINT.example.com.", "a", "xn--bcher-kva.example", "1.lab", &"a".repeat(63)] {
+            assert!(is_hostname(host), "{}", host);
+        }
+        for host in &["", ".", "-mint.lab", "mint-.lab", "mint..lab", "mint_1.lab", "192.168.1.300", "10.0.0", "mínt.lab", &"a".repeat(64),
+                      &vec!["abc"; 64].join(".")] {
+            assert!(!is_hostname(host), "{}", host);
+        }
+    }
+
+    #[test]
+    fn hosts_and_ports() {
+        let parse = |addr: &str| HostAndPort::new_from_str(addr).map(|h| (h.host().to_string(), h.port()));
+        assert_eq!(parse("192.168.1.70:7000"), Some((String::from("192.168.1.70"), 7000)));
+        assert_eq!(parse("Mint-1.Lab:7000"), Some((String::from("mint-1.lab"), 7000)));
+        assert_eq!(parse("[fe80::1]:7000"), Some((String::from("fe80::1"), 7000)));
+        assert_eq!(parse("[::1]:7000").unwrap().0, "::1");
+        assert_eq!(HostAndPort::new_from_str("[fe80::1]:7000").unwrap().to_string(), "[fe80::1]:7000");
+        assert_eq!(parse("fe80::1:7000"), None);
+        assert_eq!(parse("[fe80::1]"), None);
+        assert_eq!(parse("mint-1.lab"), None);
+        assert_eq!(parse("mint-1.lab:70000"), None);
+        assert_eq!(parse("mint 1.lab:7000"), None);
+        assert_eq!(parse(":7000"), None);
+        assert_eq!(ServerAddress::new_from_str("unix:/run/mint/admin.sock"), Some(ServerAddress::Unix(path::PathBuf::from("/run/mint/admin.sock"))));
+        assert_eq!(ServerAddress::new_from_str("unix:"), None);
+    }
+
+    #[test]
+    fn ipv6_numbers() {
+        assert_eq!(ipv6addr_to_u128(&Ipv6Addr::from_str("::1").unwrap()), 1);
+        assert_eq!(ipv6addr_to_u128(&Ipv6Addr::from_str("1::").unwrap()), 1 << 112);
+    }
+}
@@ -4,18 +4,86 @@
 
 extern crate clap;
 extern crate log;
+pub mod cli;
 pub mod config;
 pub mod ipparser;
 pub mod commands;
 pub mod clients;
+pub mod tls;
+pub mod transport;
+pub mod session;
+pub mod proxy;
+pub mod auth;
+pub mod server_config;
+pub mod dispatcher;
+pub mod simulator;
+pub mod targets;
+pub mod drift;
+pub mod apply;
+pub mod backup;
+pub mod seed;
+pub mod preflight;
+pub mod secret;
+pub mod rotate;
+#[cfg(feature = "async")]
+pub mod async_client;
 use std::process;
 
-pub fn run(command: commands::BaseCommand) {    
-    if let Ok(()) = config::setup_logging(&log::LevelFilter::Warn) {
+// The options of the subcommands that only some of them have
+#[derive(Default)]
+pub struct RunOptions {
+    // Run it even when the preflight check finds conflicts
+    pub force: bool,
+    // The new password or key was generated, it is shown instead of checked
+    pub generated: bool
+}
+
+impl RunOptions {
+    pub fn from_clap_matches(matches: &clap::ArgMatches) -> RunOptions {
+        match matches.subcommand() {
+            (_name, Some(subcommand_matches)) => RunOptions {
+                force: subcommand_matches.is_present("force"),
+                generated: subcommand_matches.is_present("generate")
+            },
+            _ => RunOptions::default()
+        }
+    }
+}
+
+pub fn run(command: commands::BaseCommand, log_config: &config::LogConfig, options: &RunOptions) {
+    if let Ok(()) = config::setup_logging(log_config) {
         log::debug!("Logging was set up");
+        if let Err(e) = preflight::check(&command, options.force) {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+        let new_secret = match command.command() {
+            commands::Command::Set(commands::Set::Password(secret)) => Some(("password", secret)),
+            commands::Command::Set(commands::Set::Key(secret)) => Some(("key", secret)),
+            _ => None
+        };
+        if let (Some((what, secret)), false) = (new_secret, options.generated) {
+            for weakness in secret::weaknesses(secret) {
+                eprintln!("Warning: the new {} is weak, {}", what, weakness);
+            }
+        }
         println!("{}", command.send_and_interpret());
+        if let (Some((what, secret)), true) = (new_secret, options.generated) {
+            println!("The new {} is {}", what, secret);
+        }
     } else {
         eprintln!("Could not set up logging");
         process::exit(1);
     }
 }
+
+// For the subcommands that act on many servers, they print their own output and return the exit code
+pub fn run_task(task: fn(&clap::ArgMatches) -> i32, matches: &clap::ArgMatches, log_config: &config::LogConfig) -> i32 {
+    if let Ok(()) = config::setup_logging(log_config) {
+        log::debug!("Logging was set up");
+        task(matches)
+    } else {
+        eprintln!("Could not set up logging");
+        1
+    }
+}
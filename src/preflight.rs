@@ -0,0 +1,118 @@
+// Author: Jorge Alarcon Alvarez
+// Email:  jorge4larcon@gmail.com
+// This module is used to check a new capacity or list size against the live state of the server
+// before setting it, some combinations make no sense with the other settings or the clients.
+
+use crate::commands;
+use crate::server_config;
+use crate::targets;
+
+// What the server has right now
+pub struct LiveState {
+    pub config: server_config::ServerConfig,
+    // Only counted for a new capacity, it needs every client
+    pub clients: Option<usize>
+}
+
+impl LiveState {
+    pub fn fetch(target: &targets::Target, command: &commands::Command) -> Result<LiveState, String> {
+        let config = target.running_config()?;
+        let clients = match command {
+            commands::Command::Set(commands::Set::Capacity(_)) => Some(target.clients()?.len()),
+            _ => None
+        };
+        Ok(LiveState { config, clients })
+    }
+}
+
+// Why the command would leave the server in a nonsensical state, empty when it would not
+pub fn conflicts(command: &commands::Command, state: &LiveState) -> Vec<String> {
+    let mut conflicts = Vec::new();
+    match command {
+        commands::Command::Set(commands::Set::Capacity(capacity)) => {
+            let capacity = *capacity as usize;
+            if let Some(clients) = state.clients.filter(|clients| *clients > capacity) {
+                conflicts.push(format!("{} clients are connected but the capacity would be {}, no new client can register until {} of them leave",
+                                       clients, capacity, clients - capacity + 1));
+            }
+            if state.config.list_size() as usize > capacity {
+                conflicts.push(format!("the list size {} would be larger than the capacity {}, the lists can never be that long",
+                                       state.config.list_size(), capacity));
+            }
+        },
+        commands::Command::Set(commands::Set::ListSize(list_size)) if *list_size > state.config.capacity() => {
+            conflicts.push(format!("the list size {} would be larger than the capacity {}, the lists can never be that long",
+                                   list_size, state.config.capacity()));
+        },
+        _ => ()
+    }
+    conflicts
+}
+
+fn is_checked(command: &commands::Command) -> bool {
+    matches!(command, commands::Command::Set(commands::Set::Capacity(_)) | commands::Command::Set(commands::Set::ListSize(_)))
+}
+
+// Refuses the command when it conflicts with the live state of the server, with force the
+// conflicts are only warnings. When the state could not be read nothing is known to conflict, it
+// is a warning and the command runs.
+pub fn check(command: &commands::BaseCommand, force: bool) -> Result<(), String> {
+    if !is_checked(command.command()) {
+        return Ok(());
+    }
+    let target = targets::Target::from_base_command(command);
+    let conflicts = match LiveState::fetch(&target, command.command()) {
+        Ok(state) => conflicts(command.command(), &state),
+        Err(e) => {
+            eprintln!("Warning: the current settings could not be checked: {}", e);
+            log::warn!(server = target.name.as_str(); "Running {} without checking it: {}", command.command().name(), e);
+            return Ok(());
+        }
+    };
+    if conflicts.is_empty() {
+        return Ok(());
+    }
+    if force {
+        for conflict in &conflicts {
+            eprintln!("Warning: {}", conflict);
+        }
+        log::warn!(server = target.name.as_str(); "Forced {} despite {} conflict(s)", command.command().name(), conflicts.len());
+        return Ok(());
+    }
+    Err(format!("Refusing to run {}:\n  {}\nUse --force to run it anyway", command.command().name(), conflicts.join("\n  ")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 100 of capacity and 20 of list size
+    fn state(clients: Option<usize>) -> LiveState {
+        LiveState { config: server_config::ServerConfig::default(), clients }
+    }
+
+    fn capacity(capacity: u16) -> commands::Command {
+        commands::Command::Set(commands::Set::Capacity(capacity))
+    }
+
+    #[test]
+    fn capacity_and_clients() {
+        assert!(conflicts(&capacity(50), &state(Some(49))).is_empty());
+        assert!(conflicts(&capacity(50), &state(Some(50))).is_empty());
+        let above = conflicts(&capacity(50), &state(Some(51)));
+        assert_eq!(above, vec![String::from("51 clients are connected but the capacity would be 50, no new client can register until 2 of them leave")]);
+        assert!(conflicts(&capacity(50), &state(None)).is_empty());
+    }
+
+    #[test]
+    fn list_size_and_capacity() {
+        assert!(conflicts(&capacity(20), &state(Some(0))).is_empty());
+        let below = conflicts(&capacity(19), &state(Some(0)));
+        assert_eq!(below, vec![String::from("the list size 20 would be larger than the capacity 19, the lists can never be that long")]);
+        assert_eq!(conflicts(&capacity(10), &state(Some(12))).len(), 2);
+        let list_size = |list_size| commands::Command::Set(commands::Set::ListSize(list_size));
+        assert!(conflicts(&list_size(100), &state(None)).is_empty());
+        assert_eq!(conflicts(&list_size(101), &state(None)).len(), 1);
+        assert!(conflicts(&commands::Command::Set(commands::Set::DropVotes(1)), &state(Some(1000))).is_empty());
+    }
+}
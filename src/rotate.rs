@@ -0,0 +1,100 @@
+// Author: Jorge Alarcon Alvarez
+// Email:  jorge4larcon@gmail.com
+// This module is used to change the admin password of the servers to a generated one without
+// losing track of which one is active, even when the reply of set-key is lost.
+
+extern crate clap;
+
+use crate::commands;
+use crate::config;
+use crate::secret;
+use crate::targets;
+
+// Returned by rotate-key: every key rotated, some server kept its old key, the key of some
+// server is unknown or its new key could not be stored
+pub const ROTATED: i32 = 0;
+pub const NOT_ROTATED: i32 = 1;
+pub const UNKNOWN_KEY: i32 = 2;
+
+// Which key the server answers to after set-key
+#[derive(Clone, PartialEq, Debug)]
+pub enum Outcome {
+    Rotated,
+    // The server replied to set-key with an error, the reason
+    Refused(String),
+    NotRotated,
+    Unknown
+}
+
+// Sets the new key and finds out which key is active: the new one is tried first, then the old
+// one. Only an error reply is trusted, any other reply can be lost after the key changed.
+pub fn rotate(target: &targets::Target, new_key: &str) -> Outcome {
+    let reply = target.command(commands::Command::Set(commands::Set::Key(new_key.to_string()))).send()
+        .and_then(|reply| serde_json::from_str::<serde_json::Value>(&reply).ok());
+    match reply {
+        Some(reply) if reply.get("error").is_some() => {
+            let name = reply.get("name").and_then(|name| name.as_str()).unwrap_or("unknown error");
+            return Outcome::Refused(format!("error code {}: {}", reply["error"], name));
+        },
+        Some(_) => log::debug!(server = target.name.as_str(); "set-key was answered, verifying the new key"),
+        None => log::warn!(server = target.name.as_str(); "set-key was not answered, verifying which key is active")
+    }
+    if target.with_password(new_key).running_config().is_ok() {
+        return Outcome::Rotated;
+    }
+    if target.running_config().is_ok() {
+        return Outcome::NotRotated;
+    }
+    Outcome::Unknown
+}
+
+pub fn rotate_key(matches: &clap::ArgMatches) -> i32 {
+    let targets = match targets::from_clap_matches(matches) {
+        Ok(targets) => targets,
+        Err(e) => {
+            eprintln!("{}", e);
+            return NOT_ROTATED;
+        }
+    };
+    let profiles_path = config::Profiles::path_from_clap_matches(matches);
+    let mut code = ROTATED;
+    for target in &targets {
+        let new_key = match secret::generate(secret::ALPHANUMERIC, secret::DEFAULT_KEY_LENGTH) {
+            Ok(new_key) => new_key,
+            Err(e) => {
+                eprintln!("{}: {}", target.name, e);
+                code = code.max(NOT_ROTATED);
+                continue;
+            }
+        };
+        match rotate(target, &new_key) {
+            Outcome::Rotated => {
+                match (&target.profile, &profiles_path) {
+                    (Some(profile), Some(path)) => match config::set_admin_password(path, profile, &new_key) {
+                        Ok(()) => println!("{}: key rotated, the profile was updated", target.name),
+                        Err(e) => {
+                            // The server already has it, it must not be lost
+                            eprintln!("{}: key rotated but the profile could not be updated: {}", target.name, e);
+                            eprintln!("{}: the new key is {}", target.name, new_key);
+                            code = code.max(UNKNOWN_KEY);
+                        }
+                    },
+                    _ => println!("{}: key rotated, the new key is {}", target.name, new_key)
+                }
+            },
+            Outcome::Refused(e) => {
+                eprintln!("{}: the key was not changed, the server replied {}", target.name, e);
+                code = code.max(NOT_ROTATED);
+            },
+            Outcome::NotRotated => {
+                eprintln!("{}: the key was not changed, the old key is still active", target.name);
+                code = code.max(NOT_ROTATED);
+            },
+            Outcome::Unknown => {
+                eprintln!("{}: neither the old nor the new key is accepted, the new key is {}", target.name, new_key);
+                code = code.max(UNKNOWN_KEY);
+            }
+        }
+    }
+    code
+}
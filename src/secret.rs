@@ -0,0 +1,140 @@
+// Author: Jorge Alarcon Alvarez
+// Email:  jorge4larcon@gmail.com
+// This module is used to generate the keys and passwords set in the servers and to tell when a
+// chosen one is weak.
+
+extern crate clap;
+extern crate rand;
+
+use rand::seq::SliceRandom;
+
+// The protocol only accepts secrets shorter than 33 characters
+pub const MAX_LENGTH: usize = 32;
+// Shorter ones are weak
+pub const MIN_LENGTH: usize = 12;
+// The clients type the password, the admin key is usually stored in a profile
+pub const DEFAULT_PASSWORD_LENGTH: usize = 16;
+pub const DEFAULT_KEY_LENGTH: usize = 32;
+pub const ALPHANUMERIC: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
+pub const HEX: &str = "0123456789abcdef";
+pub const SYMBOLS: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789!#$%&()*+,-./:;<=>?@[]^_{|}~";
+
+// One per line, compared ignoring case
+const COMMON_PASSWORDS: &str = include_str!("common_passwords.txt");
+
+// alphanumeric, hex or symbols, anything else is the list of characters to use
+pub fn alphabet_from_str(alphabet: &str) -> &str {
+    match alphabet {
+        "alphanumeric" => ALPHANUMERIC,
+        "hex" => HEX,
+        "symbols" => SYMBOLS,
+        characters => characters
+    }
+}
+
+pub fn is_valid_length(length: usize) -> bool {
+    length > 0 && length <= MAX_LENGTH
+}
+
+pub fn is_valid_alphabet(alphabet: &str) -> bool {
+    let alphabet = alphabet_from_str(alphabet);
+    alphabet.chars().all(|c| c.is_ascii_graphic()) && unique_chars(alphabet).len() > 1
+}
+
+fn unique_chars(alphabet: &str) -> Vec<char> {
+    let mut chars: Vec<char> = Vec::new();
+    for c in alphabet.chars() {
+        if !chars.contains(&c) {
+            chars.push(c);
+        }
+    }
+    chars
+}
+
+// A random secret of length characters of the alphabet, using the OS seeded generator
+pub fn generate(alphabet: &str, length: usize) -> Result<String, String> {
+    if !is_valid_length(length) {
+        return Err(format!("The length must be between [1,{}]", MAX_LENGTH));
+    }
+    if !is_valid_alphabet(alphabet) {
+        return Err(String::from("The alphabet must have at least 2 different printable ASCII characters"));
+    }
+    let alphabet = unique_chars(alphabet_from_str(alphabet));
+    let mut rng = rand::thread_rng();
+    Ok((0..length).filter_map(|_| alphabet.choose(&mut rng)).collect())
+}
+
+// The --generate, --length and --alphabet options of set-password and set-key
+pub fn from_clap_matches(subcommand_matches: &clap::ArgMatches, default_length: usize) -> Result<String, String> {
+    let length = match subcommand_matches.value_of("length") {
+        Some(length) => length.parse::<usize>().map_err(|e| e.to_string())?,
+        None => default_length
+    };
+    generate(subcommand_matches.value_of("alphabet").unwrap_or("alphanumeric"), length)
+}
+
+pub fn is_common(secret: &str) -> bool {
+    COMMON_PASSWORDS.lines().any(|common| common.eq_ignore_ascii_case(secret))
+}
+
+// Why the secret is easy to guess, empty when it is not
+pub fn weaknesses(secret: &str) -> Vec<String> {
+    let mut weaknesses = Vec::new();
+    if is_common(secret) {
+        weaknesses.push(String::from("it is one of the most common passwords"));
+    }
+    if secret.len() < MIN_LENGTH {
+        weaknesses.push(format!("it has {} characters, less than {}", secret.len(), MIN_LENGTH));
+    }
+    let classes = [
+        secret.chars().any(|c| c.is_ascii_lowercase()),
+        secret.chars().any(|c| c.is_ascii_uppercase()),
+        secret.chars().any(|c| c.is_ascii_digit()),
+        secret.chars().any(|c| c.is_ascii_punctuation() || c == ' ')
+    ].iter().filter(|class| **class).count();
+    if classes < 3 {
+        weaknesses.push(format!("it mixes {} of lowercase, uppercase, digits and symbols, at least 3 are expected", classes));
+    }
+    weaknesses
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli;
+
+    #[test]
+    fn generated_secrets() {
+        let secret = generate("hex", 20).unwrap();
+        assert_eq!(secret.len(), 20);
+        assert!(secret.chars().all(|c| HEX.contains(c)));
+        assert!(generate("ab", MAX_LENGTH).unwrap().chars().all(|c| c == 'a' || c == 'b'));
+        assert!(generate("alphanumeric", 0).is_err());
+        assert!(generate("alphanumeric", MAX_LENGTH + 1).is_err());
+        assert!(generate("aaa", 10).is_err());
+        assert!(generate("ab\n", 10).is_err());
+    }
+
+    #[test]
+    fn generate_options() {
+        let matches = cli::build_app().get_matches_from_safe(vec!["admint", "set-key", "--generate"]).unwrap();
+        let key = from_clap_matches(matches.subcommand_matches("set-key").unwrap(), DEFAULT_KEY_LENGTH).unwrap();
+        assert_eq!(key.len(), DEFAULT_KEY_LENGTH);
+        assert!(key.chars().all(|c| ALPHANUMERIC.contains(c)));
+        let matches = cli::build_app().get_matches_from_safe(vec!["admint", "set-password", "--generate", "--alphabet", "symbols", "--length", "12"]).unwrap();
+        let password = from_clap_matches(matches.subcommand_matches("set-password").unwrap(), DEFAULT_PASSWORD_LENGTH).unwrap();
+        assert_eq!(password.len(), 12);
+        assert!(password.chars().all(|c| SYMBOLS.contains(c)));
+        assert!(cli::build_app().get_matches_from_safe(vec!["admint", "set-key", "--generate", "--length", "33"]).is_err());
+        assert!(cli::build_app().get_matches_from_safe(vec!["admint", "set-key", "--generate", "--alphabet", "aaa"]).is_err());
+    }
+
+    #[test]
+    fn weak_secrets() {
+        assert_eq!(weaknesses("LetMeIn").len(), 3);
+        assert_eq!(weaknesses("password").len(), 3);
+        assert!(is_common("PASSWORD"));
+        assert_eq!(weaknesses("lowercase-only-but-long").len(), 1);
+        assert!(weaknesses("Gr8-Long-Passphrase").is_empty());
+    }
+}
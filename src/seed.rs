@@ -0,0 +1,286 @@
+// Author: Jorge Alarcon Alvarez
+// Email:  jorge4larcon@gmail.com
+// This module is used to fill a ClientsMap with synthetic clients and to save it as a snapshot, so
+// the simulator and the tests can start with the same clients every time.
+
+extern crate rand;
+extern crate rand_chacha;
+
+use crate::clients;
+use crate::ipparser;
+use rand::Rng;
+use rand::SeedableRng;
+use std::collections;
+use std::fmt;
+use std::fs;
+use std::path;
+use std::str::FromStr;
+
+const ADJECTIVES: [&str; 16] = [
+    "quiet", "brave", "lucky", "rapid", "sunny", "misty", "clever", "silent",
+    "golden", "frozen", "wild", "gentle", "cosmic", "rusty", "happy", "little"
+];
+const NOUNS: [&str; 16] = [
+    "fox", "falcon", "river", "comet", "badger", "maple", "otter", "pixel",
+    "tiger", "cedar", "raven", "harbor", "panda", "nebula", "wolf", "lynx"
+];
+const SEPARATORS: [&str; 2] = ["_", "-"];
+const NUMBERS: usize = 10_000;
+// The different usernames that can be made
+const USERNAMES: usize = ADJECTIVES.len() * NOUNS.len() * SEPARATORS.len() * NUMBERS;
+// Tries of a MAC address and an IPv4 address per client before giving up, the addresses are
+// picked at random so a few of them are always taken already
+const ATTEMPTS_PER_CLIENT: usize = 1000;
+
+// An IPv4 network like 10.0.0.0/24, the clients get the addresses between the network and the
+// broadcast addresses
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Subnet {
+    network: u32,
+    prefix: u8
+}
+
+impl fmt::Display for Subnet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", ipparser::u32_to_ipv4(self.network), self.prefix)
+    }
+}
+
+impl FromStr for Subnet {
+    type Err = String;
+
+    fn from_str(subnet: &str) -> Result<Subnet, String> {
+        Subnet::new_from_str(subnet).ok_or(format!("{} is not a valid subnet, use IPv4/PREFIX, e.g. 10.0.0.0/24", subnet))
+    }
+}
+
+impl Subnet {
+    pub fn new_from_str(subnet: &str) -> Option<Subnet> {
+        let (address, prefix) = subnet.split_once('/')?;
+        let address = ipparser::ipv4_to_u32(address)?;
+        let prefix = prefix.parse::<u8>().ok().filter(|prefix| *prefix <= 30)?;
+        Some(Subnet { network: address & Subnet::mask(prefix), prefix })
+    }
+
+    fn mask(prefix: u8) -> u32 {
+        u32::MAX.checked_shl(32 - u32::from(prefix)).unwrap_or(0)
+    }
+
+    // The addresses that can be given to a client
+    pub fn hosts(&self) -> u64 {
+        (1u64 << (32 - self.prefix)) - 2
+    }
+
+    fn host(&self, index: u64) -> u32 {
+        self.network + index as u32 + 1
+    }
+
+    fn contains(&self, other: &Subnet) -> bool {
+        self.prefix <= other.prefix && other.network & Subnet::mask(self.prefix) == self.network
+    }
+}
+
+// Two subnets either do not overlap or one contains the other, so only the ones that are not
+// inside another one are kept, otherwise their addresses would be counted twice
+fn merge_subnets(subnets: &[Subnet]) -> Vec<Subnet> {
+    let mut sorted = subnets.to_vec();
+    sorted.sort_by_key(|subnet| (subnet.network, subnet.prefix));
+    let mut merged: Vec<Subnet> = Vec::new();
+    for subnet in sorted {
+        if !merged.last().is_some_and(|last| last.contains(&subnet)) {
+            merged.push(subnet);
+        }
+    }
+    merged
+}
+
+pub fn is_subnet(subnet: String) -> Result<(), String> {
+    subnet.parse::<Subnet>().map(|_| ())
+}
+
+pub struct SeedOptions {
+    pub count: usize,
+    pub seed: u64,
+    pub subnets: Vec<Subnet>,
+    // The share of clients that can only be found by their MAC address, between 0 and 1
+    pub get_only_by_mac_ratio: f64,
+    pub max_drop_votes: u8
+}
+
+impl Default for SeedOptions {
+    fn default() -> Self {
+        SeedOptions {
+            count: 100,
+            seed: 0,
+            subnets: vec![Subnet { network: 0xC0A8_0000, prefix: 16 }],
+            get_only_by_mac_ratio: 0.2,
+            max_drop_votes: 0
+        }
+    }
+}
+
+// The same options always give the same clients
+pub fn generate(options: &SeedOptions) -> Result<clients::ClientsMap, String> {
+    if options.subnets.is_empty() {
+        return Err(String::from("At least one subnet is needed"));
+    }
+    let subnets = merge_subnets(&options.subnets);
+    let hosts: u64 = subnets.iter().map(|subnet| subnet.hosts()).sum();
+    if options.count as u64 > hosts {
+        return Err(format!("{} clients do not fit in {} address(es)", options.count, hosts));
+    }
+    if options.count > USERNAMES {
+        return Err(format!("{} clients are too many, only {} different usernames can be made", options.count, USERNAMES));
+    }
+    // ChaCha8Rng gives the same numbers in every platform and version of rand, unlike StdRng
+    let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(options.seed);
+    let mut clients_map = clients::ClientsMap::new();
+    let mut ipv4_addrs = collections::HashSet::new();
+    let mut usernames = collections::HashSet::new();
+    let max_attempts = options.count.saturating_mul(ATTEMPTS_PER_CLIENT);
+    let mut attempts = 0;
+    while clients_map.len() < options.count {
+        if attempts == max_attempts {
+            return Err(format!("Only {} of {} clients could be made after {} attempts", clients_map.len(), options.count, attempts));
+        }
+        attempts += 1;
+        let mac = match ipparser::MacAddress::new(random_mac(&mut rng)) {
+            Some(mac) if !clients_map.exists_by_mac(&mac) => mac,
+            _ => continue
+        };
+        let ipv4_addr = random_ipv4_addr(&mut rng, &subnets, hosts);
+        if !ipv4_addrs.insert(ipv4_addr) {
+            continue;
+        }
+        let username = random_username(&mut rng, &usernames).ok_or("There are no usernames left")?;
+        usernames.insert(username.clone());
+        let port = rng.gen_range(1024..=u16::MAX);
+        let get_only_by_mac = rng.gen_bool(options.get_only_by_mac_ratio.clamp(0.0, 1.0));
+        let drop_votes = rng.gen_range(0..=options.max_drop_votes);
+        match clients::Client::new(ipv4_addr, port, &username, get_only_by_mac, drop_votes) {
+            Some(client) => {
+                clients_map.insert(&mac, &client);
+            },
+            None => return Err(format!("{} is not a valid username", username))
+        }
+    }
+    Ok(clients_map)
+}
+
+// Unicast and locally administered, so they never clash with a real network card
+fn random_mac(rng: &mut impl Rng) -> u64 {
+    let mac = rng.gen::<u64>() & 0x0000_FFFF_FFFF_FFFF;
+    (mac | 0x0000_0200_0000_0000) & !0x0000_0100_0000_0000
+}
+
+// Every address of every subnet has the same chance
+fn random_ipv4_addr(rng: &mut impl Rng, subnets: &[Subnet], hosts: u64) -> u32 {
+    let mut index = rng.gen_range(0..hosts);
+    for subnet in subnets {
+        if index < subnet.hosts() {
+            return subnet.host(index);
+        }
+        index -= subnet.hosts();
+    }
+    subnets[0].host(0)
+}
+
+// A random username, or the next one that is not taken, None once all of them are taken
+fn random_username(rng: &mut impl Rng, taken: &collections::HashSet<String>) -> Option<String> {
+    if taken.len() >= USERNAMES {
+        return None;
+    }
+    let first = rng.gen_range(0..USERNAMES);
+    (0..USERNAMES).map(|i| username((first + i) % USERNAMES)).find(|username| !taken.contains(username))
+}
+
+// Every index below USERNAMES gives a different username
+fn username(index: usize) -> String {
+    let number = index % NUMBERS;
+    let index = index / NUMBERS;
+    let separator = SEPARATORS[index % SEPARATORS.len()];
+    let index = index / SEPARATORS.len();
+    format!("{}{}{}{}", ADJECTIVES[index / NOUNS.len()], separator, NOUNS[index % NOUNS.len()], number)
+}
+
+// A snapshot is {"clients":[{"mac":...,"ipv4_addr":...,"port":...,"username":...,"get_only_by_mac":...,"drop_votes":...}]}
+pub fn to_snapshot(clients_map: &clients::ClientsMap) -> serde_json::Value {
+    let clients: Vec<serde_json::Value> = clients_map.range(0, clients_map.len()).iter().map(|(mac, client)| {
+        serde_json::json!({
+            "mac": mac.to_string(),
+            "ipv4_addr": ipparser::u32_to_ipv4(client.ipv4_addr).to_string(),
+            "port": client.port,
+            "username": client.username,
+            "get_only_by_mac": client.get_only_by_mac,
+            "drop_votes": client.drop_votes
+        })
+    }).collect();
+    serde_json::json!({ "clients": clients })
+}
+
+pub fn from_snapshot(snapshot: &serde_json::Value) -> Result<clients::ClientsMap, String> {
+    let clients = snapshot.get("clients").and_then(|clients| clients.as_array()).ok_or("The snapshot has no clients list")?;
+    let mut clients_map = clients::ClientsMap::new();
+    for (i, value) in clients.iter().enumerate() {
+        let mac = value.get("mac").and_then(|mac| mac.as_str()).and_then(ipparser::MacAddress::new_from_str)
+            .ok_or(format!("The client {} of the snapshot has no valid MAC address", i))?;
+        let client = clients::Client::from_json_value(value).ok_or(format!("The client {} of the snapshot is not valid", i))?;
+        clients_map.insert(&mac, &client);
+    }
+    Ok(clients_map)
+}
+
+pub fn write_snapshot(path: &path::Path, clients_map: &clients::ClientsMap) -> Result<(), String> {
+    let contents = serde_json::to_string_pretty(&to_snapshot(clients_map)).map_err(|e| e.to_string())?;
+    fs::write(path, contents + "\n").map_err(|e| format!("Could not write {}: {}", path.display(), e))
+}
+
+pub fn load_snapshot(path: &path::Path) -> Result<clients::ClientsMap, String> {
+    let contents = fs::read_to_string(path).map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+    let snapshot = serde_json::from_str::<serde_json::Value>(&contents).map_err(|e| format!("{}: {}", path.display(), e))?;
+    from_snapshot(&snapshot).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seed_options(count: usize, seed: u64, subnets: &[&str]) -> SeedOptions {
+        SeedOptions {
+            count,
+            seed,
+            subnets: subnets.iter().map(|subnet| Subnet::new_from_str(subnet).unwrap()).collect(),
+            max_drop_votes: 3,
+            ..SeedOptions::default()
+        }
+    }
+
+    #[test]
+    fn same_seed_same_clients() {
+        let clients_map = generate(&seed_options(50, 40, &["10.0.0.0/24"])).unwrap();
+        assert_eq!(clients_map.len(), 50);
+        assert_eq!(to_snapshot(&clients_map), to_snapshot(&generate(&seed_options(50, 40, &["10.0.0.0/24"])).unwrap()));
+        assert_ne!(to_snapshot(&clients_map), to_snapshot(&generate(&seed_options(50, 41, &["10.0.0.0/24"])).unwrap()));
+    }
+
+    #[test]
+    fn overlapping_subnets() {
+        let subnets = ["10.0.0.0/30", "10.0.1.0/24", "10.0.0.0/30", "10.0.1.128/25"];
+        let merged = merge_subnets(&seed_options(0, 0, &subnets).subnets);
+        assert_eq!(merged.iter().map(|subnet| subnet.to_string()).collect::<Vec<String>>(), vec!["10.0.0.0/30", "10.0.1.0/24"]);
+        assert!(generate(&seed_options(3, 0, &["10.0.0.0/30", "10.0.0.0/30"])).is_err());
+        assert_eq!(generate(&seed_options(2, 0, &["10.0.0.0/30", "10.0.0.0/30"])).unwrap().len(), 2);
+        assert_eq!(generate(&seed_options(256, 0, &subnets)).unwrap().len(), 256);
+    }
+
+    #[test]
+    fn usernames() {
+        assert_eq!(username(0), "quiet_fox0");
+        assert_eq!(username(USERNAMES - 1), "little-lynx9999");
+        let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(0);
+        let taken: collections::HashSet<String> = (0..NUMBERS).map(username).collect();
+        let username = random_username(&mut rng, &taken).unwrap();
+        assert!(!taken.contains(&username));
+        assert!(generate(&seed_options(USERNAMES + 1, 0, &["10.0.0.0/1"])).is_err());
+    }
+}
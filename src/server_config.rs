@@ -0,0 +1,417 @@
+// Author: Jorge Alarcon Alvarez
+// Email:  jorge4larcon@gmail.com
+// This module is used to represent the configuration of a MINT server, it can not hold a value
+// the server would refuse and it reads and writes the running_config text of the server.
+
+use serde::Deserialize;
+use std::fmt;
+use std::fs;
+use std::path;
+use std::str::FromStr;
+
+pub const CAPACITY_ERROR: &str = "This value must be between [2,65535]";
+pub const DROP_VOTES_ERROR: &str = "This value must be between [1,255]";
+pub const SECRET_ERROR: &str = "This value must have less than 33 characters and all must be printable ascii";
+
+pub fn is_valid_capacity(capacity: u16) -> bool {
+    capacity >= 2
+}
+
+pub fn is_valid_drop_votes(drop_votes: u8) -> bool {
+    drop_votes >= 1
+}
+
+// The password of the clients and the password of the admin (the key), a line break or any other
+// control character would break the running_config text
+pub fn is_valid_secret(secret: &str) -> bool {
+    secret.len() < 33 && secret.chars().all(|c| c.is_ascii_graphic() || c == ' ')
+}
+
+// The settings of the running config, by the name used in the command line
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Setting {
+    Capacity,
+    ListSize,
+    DropVotes,
+    DropVerification,
+    Password,
+    Key
+}
+
+pub const SETTING_NAMES: [&str; 6] = ["capacity", "list-size", "drop-votes", "drop-verification", "password", "key"];
+
+impl Setting {
+    pub const ALL: [Setting; 6] = [
+        Setting::Capacity, Setting::ListSize, Setting::DropVotes, Setting::DropVerification, Setting::Password, Setting::Key
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Setting::Capacity => SETTING_NAMES[0],
+            Setting::ListSize => SETTING_NAMES[1],
+            Setting::DropVotes => SETTING_NAMES[2],
+            Setting::DropVerification => SETTING_NAMES[3],
+            Setting::Password => SETTING_NAMES[4],
+            Setting::Key => SETTING_NAMES[5]
+        }
+    }
+
+    // The name of the field in the requests and in the JSON and TOML outputs
+    pub fn field(&self) -> &'static str {
+        match self {
+            Setting::Capacity => "capacity",
+            Setting::ListSize => "list_size",
+            Setting::DropVotes => "drop_votes",
+            Setting::DropVerification => "drop_verification",
+            Setting::Password => "password",
+            Setting::Key => "key"
+        }
+    }
+
+    // The password of the clients and the key are never shown in the comparisons
+    pub fn is_secret(&self) -> bool {
+        matches!(self, Setting::Password | Setting::Key)
+    }
+}
+
+impl fmt::Display for Setting {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for Setting {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Setting, String> {
+        Setting::ALL.iter().copied()
+            .find(|setting| setting.name() == s || setting.field() == s)
+            .ok_or(format!("{} is not a setting, use one of {}", s, SETTING_NAMES.join(", ")))
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ConfigFormat {
+    Text,
+    Json,
+    Toml
+}
+
+impl FromStr for ConfigFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<ConfigFormat, String> {
+        match s {
+            "text" => Ok(ConfigFormat::Text),
+            "json" => Ok(ConfigFormat::Json),
+            "toml" => Ok(ConfigFormat::Toml),
+            _ => Err(format!("{} is not a valid format, use text, json or toml", s))
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct ServerConfig {
+    capacity: u16,
+    list_size: u16,
+    drop_votes: u8,
+    drop_verification: bool,
+    // The password of the clients
+    password: String,
+    // The password of the admin
+    key: String
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            capacity: 100,
+            list_size: 20,
+            drop_votes: 3,
+            drop_verification: true,
+            password: String::new(),
+            key: String::from("admin")
+        }
+    }
+}
+
+// The running_config text, one "name: value" line per setting
+impl fmt::Display for ServerConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "capacity: {}\nlist size: {}\ndrop votes: {}\ndrop verification: {}\npassword: {}\nkey: {}",
+               self.capacity, self.list_size, self.drop_votes, self.drop_verification, self.password, self.key)
+    }
+}
+
+impl ServerConfig {
+    pub fn new(capacity: u16, list_size: u16, drop_votes: u8, drop_verification: bool, password: &str, key: &str) -> Result<ServerConfig, String> {
+        let mut config = ServerConfig { list_size, drop_verification, ..ServerConfig::default() };
+        config.set_capacity(capacity)?;
+        config.set_drop_votes(drop_votes)?;
+        config.set_password(password)?;
+        config.set_key(key)?;
+        Ok(config)
+    }
+
+    pub fn capacity(&self) -> u16 {
+        self.capacity
+    }
+
+    pub fn list_size(&self) -> u16 {
+        self.list_size
+    }
+
+    pub fn drop_votes(&self) -> u8 {
+        self.drop_votes
+    }
+
+    pub fn drop_verification(&self) -> bool {
+        self.drop_verification
+    }
+
+    pub fn password(&self) -> &str {
+        &self.password
+    }
+
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    pub fn set_capacity(&mut self, capacity: u16) -> Result<(), String> {
+        if !is_valid_capacity(capacity) {
+            return Err(format!("Invalid capacity {}: {}", capacity, CAPACITY_ERROR));
+        }
+        self.capacity = capacity;
+        Ok(())
+    }
+
+    pub fn set_list_size(&mut self, list_size: u16) {
+        self.list_size = list_size;
+    }
+
+    pub fn set_drop_votes(&mut self, drop_votes: u8) -> Result<(), String> {
+        if !is_valid_drop_votes(drop_votes) {
+            return Err(format!("Invalid drop votes {}: {}", drop_votes, DROP_VOTES_ERROR));
+        }
+        self.drop_votes = drop_votes;
+        Ok(())
+    }
+
+    pub fn set_drop_verification(&mut self, drop_verification: bool) {
+        self.drop_verification = drop_verification;
+    }
+
+    pub fn set_password(&mut self, password: &str) -> Result<(), String> {
+        if !is_valid_secret(password) {
+            return Err(format!("Invalid password: {}", SECRET_ERROR));
+        }
+        self.password = password.to_string();
+        Ok(())
+    }
+
+    pub fn set_key(&mut self, key: &str) -> Result<(), String> {
+        if !is_valid_secret(key) {
+            return Err(format!("Invalid key: {}", SECRET_ERROR));
+        }
+        self.key = key.to_string();
+        Ok(())
+    }
+
+    pub fn running_config(&self) -> String {
+        self.to_string()
+    }
+
+    pub fn get(&self, setting: Setting) -> serde_json::Value {
+        match setting {
+            Setting::Capacity => serde_json::Value::from(self.capacity),
+            Setting::ListSize => serde_json::Value::from(self.list_size),
+            Setting::DropVotes => serde_json::Value::from(self.drop_votes),
+            Setting::DropVerification => serde_json::Value::from(self.drop_verification),
+            Setting::Password => serde_json::Value::from(self.password.as_str()),
+            Setting::Key => serde_json::Value::from(self.key.as_str())
+        }
+    }
+
+    // The given settings as {"capacity":100,"list_size":20,...}
+    pub fn to_json_value(&self, settings: &[Setting]) -> serde_json::Value {
+        let fields: serde_json::Map<String, serde_json::Value> = settings.iter()
+            .map(|setting| (setting.field().to_string(), self.get(*setting)))
+            .collect();
+        serde_json::Value::Object(fields)
+    }
+
+    // One setting or all of them
+    pub fn render(&self, setting: Option<Setting>, format: ConfigFormat) -> String {
+        let settings = match setting {
+            Some(setting) => vec![setting],
+            None => Setting::ALL.to_vec()
+        };
+        match (format, setting) {
+            (ConfigFormat::Text, None) => self.running_config(),
+            (ConfigFormat::Text, Some(setting)) => match self.get(setting) {
+                serde_json::Value::String(value) => value,
+                value => value.to_string()
+            },
+            (ConfigFormat::Json, _) => serde_json::to_string_pretty(&self.to_json_value(&settings)).unwrap_or_default(),
+            (ConfigFormat::Toml, _) => toml::to_string(&self.to_json_value(&settings)).unwrap_or_default().trim_end().to_string()
+        }
+    }
+
+    // The configuration in a reply {"result":"ok","running_config":"..."}
+    pub fn from_reply(reply: &serde_json::Value) -> Result<ServerConfig, String> {
+        match reply.get("running_config") {
+            Some(serde_json::Value::String(running_config)) => ServerConfig::from_running_config(running_config),
+            Some(_) => Err(String::from("running_config is not a string")),
+            None => Err(String::from("The reply has no running_config"))
+        }
+    }
+
+    // Every setting must be there, the lines of settings this version does not know are skipped
+    pub fn from_running_config(running_config: &str) -> Result<ServerConfig, String> {
+        let mut capacity = None;
+        let mut list_size = None;
+        let mut drop_votes = None;
+        let mut drop_verification = None;
+        let mut password = None;
+        let mut key = None;
+        for line in running_config.lines() {
+            let (name, value) = match line.split_once(':') {
+                Some((name, value)) => (name.trim(), value.strip_prefix(' ').unwrap_or(value)),
+                None if line.trim().is_empty() => continue,
+                None => return Err(format!("The line \"{}\" is not a setting", line))
+            };
+            match name {
+                "capacity" => capacity = Some(parse_setting::<u16>(name, value)?),
+                "list size" => list_size = Some(parse_setting::<u16>(name, value)?),
+                "drop votes" => drop_votes = Some(parse_setting::<u8>(name, value)?),
+                "drop verification" => drop_verification = Some(parse_setting::<bool>(name, value)?),
+                "password" => password = Some(value),
+                "key" => key = Some(value),
+                _ => log::debug!("Skipping the unknown setting {}", name)
+            }
+        }
+        match (capacity, list_size, drop_votes, drop_verification, password, key) {
+            (Some(capacity), Some(list_size), Some(drop_votes), Some(drop_verification), Some(password), Some(key)) => {
+                ServerConfig::new(capacity, list_size, drop_votes, drop_verification, password, key)
+            },
+            _ => Err(String::from("The running config is missing some settings"))
+        }
+    }
+}
+
+fn parse_setting<T: std::str::FromStr>(name: &str, value: &str) -> Result<T, String> {
+    value.trim().parse::<T>().map_err(|_| format!("{} is not a valid value for {}", value.trim(), name))
+}
+
+// The non-secret settings a server should have, only the ones given are checked or changed. It is
+// the baseline of config-diff and the desired state of apply, e.g.:
+// capacity = 100
+// list-size = 20
+// drop-votes = 3
+// drop-verification = true
+#[derive(Deserialize, Default, Clone, PartialEq, Debug)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct DesiredConfig {
+    pub capacity: Option<u16>,
+    pub list_size: Option<u16>,
+    pub drop_votes: Option<u8>,
+    pub drop_verification: Option<bool>
+}
+
+impl DesiredConfig {
+    pub fn load(path: &path::Path) -> Result<DesiredConfig, String> {
+        match fs::read_to_string(path) {
+            Ok(contents) => DesiredConfig::from_toml_str(&contents).map_err(|e| format!("{}: {}", path.display(), e)),
+            Err(e) => Err(format!("Could not read {}: {}", path.display(), e))
+        }
+    }
+
+    pub fn from_toml_str(contents: &str) -> Result<DesiredConfig, String> {
+        let desired = toml::from_str::<DesiredConfig>(contents).map_err(|e| e.to_string())?;
+        desired.validate()?;
+        Ok(desired)
+    }
+
+    // The same invariants of ServerConfig
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(capacity) = self.capacity.filter(|capacity| !is_valid_capacity(*capacity)) {
+            return Err(format!("Invalid capacity {}: {}", capacity, CAPACITY_ERROR));
+        }
+        if let Some(drop_votes) = self.drop_votes.filter(|drop_votes| !is_valid_drop_votes(*drop_votes)) {
+            return Err(format!("Invalid drop votes {}: {}", drop_votes, DROP_VOTES_ERROR));
+        }
+        Ok(())
+    }
+
+    // The non-secret settings of a running config
+    pub fn from_server_config(config: &ServerConfig) -> DesiredConfig {
+        DesiredConfig {
+            capacity: Some(config.capacity()),
+            list_size: Some(config.list_size()),
+            drop_votes: Some(config.drop_votes()),
+            drop_verification: Some(config.drop_verification())
+        }
+    }
+
+    pub fn get(&self, setting: Setting) -> Option<serde_json::Value> {
+        match setting {
+            Setting::Capacity => self.capacity.map(serde_json::Value::from),
+            Setting::ListSize => self.list_size.map(serde_json::Value::from),
+            Setting::DropVotes => self.drop_votes.map(serde_json::Value::from),
+            Setting::DropVerification => self.drop_verification.map(serde_json::Value::from),
+            Setting::Password | Setting::Key => None
+        }
+    }
+
+    // The settings that were given, in the order of Setting::ALL
+    pub fn settings(&self) -> Vec<Setting> {
+        Setting::ALL.iter().copied().filter(|setting| self.get(*setting).is_some()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn secrets() {
+        assert!(is_valid_secret(""));
+        assert!(is_valid_secret("with spaces and ~!@#"));
+        assert!(is_valid_secret(&"k".repeat(32)));
+        assert!(!is_valid_secret(&"k".repeat(33)));
+        assert!(!is_valid_secret("k3y\ncapacity: 2"));
+        assert!(!is_valid_secret("k3y\t"));
+        assert!(!is_valid_secret("a\u{e9}"));
+    }
+
+    #[test]
+    fn refuses_invalid_values() {
+        assert!(ServerConfig::new(1, 20, 3, true, "", "admin").is_err());
+        assert!(ServerConfig::new(2, 20, 0, true, "", "admin").is_err());
+        assert!(ServerConfig::new(2, 20, 1, true, "", "admin\n").is_err());
+        let mut config = ServerConfig::default();
+        assert!(config.set_capacity(0).is_err());
+        assert!(config.set_drop_votes(0).is_err());
+        assert!(config.set_password("pass\nkey: other").is_err());
+        assert!(config.set_key(&"k".repeat(33)).is_err());
+        assert_eq!(config, ServerConfig::default());
+        assert!(config.set_key("other").is_ok());
+        assert_eq!(config.key(), "other");
+    }
+
+    #[test]
+    fn running_config_round_trip() {
+        let config = ServerConfig::new(250, 0, 9, false, "with spaces ", "k3y:x").unwrap();
+        assert_eq!(ServerConfig::from_running_config(&config.running_config()), Ok(config));
+        assert!(ServerConfig::from_running_config("capacity: 1\nlist size: 2\ndrop votes: 3\ndrop verification: true\npassword: \nkey: k").is_err());
+        assert!(ServerConfig::from_running_config("capacity: 10").is_err());
+    }
+
+    #[test]
+    fn desired_config() {
+        assert_eq!(DesiredConfig::from_toml_str("capacity = 150\ndrop-votes = 2\n").unwrap().settings(), vec![Setting::Capacity, Setting::DropVotes]);
+        assert!(DesiredConfig::from_toml_str("capacity = 1\n").is_err());
+        assert!(DesiredConfig::from_toml_str("drop-votes = 0\n").is_err());
+        assert!(DesiredConfig::from_toml_str("password = \"secret\"\n").is_err());
+    }
+}
@@ -0,0 +1,204 @@
+// Author: Jorge Alarcon Alvarez
+// Email:  jorge4larcon@gmail.com
+// This module is used to send many commands to a server through the same connection.
+
+use crate::auth;
+use crate::commands;
+use crate::ipparser;
+use crate::transport;
+use std::io;
+use std::mem;
+use std::time;
+
+// Keeps the connection open between commands. The server replies every request with one JSON
+// document, so the replies are taken from the stream in the order the requests were sent, no
+// matter how they were split or joined by the network. Servers that close the connection after
+// every reply are detected on the first reused connection and from then on every request gets a
+// new connection.
+pub struct Session {
+    password: String,
+    address: ipparser::ServerAddress,
+    options: commands::ConnectionOptions,
+    transport: Option<Box<dyn transport::Transport>>,
+    // Bytes received after the end of the last reply
+    pending: Vec<u8>,
+    // Replies received through the current connection
+    replies: usize,
+    keep_alive: bool
+}
+
+impl Session {
+    pub fn new(password: String, address: ipparser::ServerAddress, options: commands::ConnectionOptions) -> Session {
+        Session { password, address, options, transport: None, pending: Vec::new(), replies: 0, keep_alive: true }
+    }
+
+    // Starts with an already established connection, the next ones are opened with `options`
+    pub fn with_transport(password: String, address: ipparser::ServerAddress, options: commands::ConnectionOptions,
+                          transport: Box<dyn transport::Transport>) -> Session {
+        let mut session = Session::new(password, address, options);
+        session.transport = Some(transport);
+        session
+    }
+
+    // false once the server was seen closing the connection after a reply
+    pub fn keeps_connection(&self) -> bool {
+        self.keep_alive
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.transport.is_some()
+    }
+
+    pub fn send_and_interpret(&mut self, command: commands::Command) -> String {
+        let base_command = commands::BaseCommand::new(self.password.clone(), self.address.clone(), command, self.options.clone());
+        let reply = self.send(&base_command);
+        base_command.interpret(reply)
+    }
+
+    // Like commands::BaseCommand::send_and_parse
+    pub fn send_and_parse(&mut self, command: commands::Command) -> Result<serde_json::Value, String> {
+        let base_command = commands::BaseCommand::new(self.password.clone(), self.address.clone(), command, self.options.clone());
+        let reply = self.send(&base_command);
+        base_command.parse(reply)
+    }
+
+    pub fn close(&mut self) {
+        if self.transport.take().is_some() {
+            log::debug!(server:% = self.address; "Session connection with {} closed", self.address);
+        }
+        self.pending.clear();
+        self.replies = 0;
+    }
+
+    fn send(&mut self, base_command: &commands::BaseCommand) -> Option<String> {
+        match base_command.options().auth {
+            commands::AuthMode::Password => self.round_trip(base_command, &base_command.to_json_string(), Leg::Only),
+            commands::AuthMode::Challenge => {
+                let reply = self.round_trip(base_command, auth::CHALLENGE_REQUEST, Leg::Challenge)?;
+                match base_command.challenge_response(&reply) {
+                    Some(signed_request) => self.round_trip(base_command, &signed_request, Leg::Response),
+                    None => {
+                        if !self.keep_alive {
+                            self.close();
+                        }
+                        Some(reply)
+                    }
+                }
+            }
+        }
+    }
+
+    fn round_trip(&mut self, base_command: &commands::BaseCommand, request: &str, leg: Leg) -> Option<String> {
+        let command = base_command.command().name();
+        log::debug!("request:\n{}", request);
+        loop {
+            let reused = self.transport.is_some() && self.replies > 0;
+            let transport = match self.transport.take() {
+                Some(transport) => transport,
+                None if leg == Leg::Response => {
+                    log::error!(server:% = self.address, command; "The connection with {} was closed before the challenge was answered", self.address);
+                    return None;
+                },
+                None => {
+                    self.close();
+                    base_command.open_transport()?
+                }
+            };
+            let transport = self.transport.insert(transport);
+            let started = time::Instant::now();
+            match exchange(transport.as_mut(), &mut self.pending, request.as_bytes()) {
+                Ok(reply) => {
+                    let latency_ms = started.elapsed().as_millis() as u64;
+                    self.replies += 1;
+                    log::info!(server:% = self.address, command, latency_ms, reused; "{} byte(s) received", reply.len());
+                    if !self.keep_alive && leg != Leg::Challenge {
+                        self.close();
+                    }
+                    let reply = String::from_utf8_lossy(&reply).to_string();
+                    log::debug!("raw reply received:\n{}", reply);
+                    return Some(reply);
+                },
+                Err(e) => {
+                    self.close();
+                    if reused && leg != Leg::Response && is_closed_by_peer(&e) {
+                        log::info!(server:% = self.address, command;
+                                   "{} closed the connection after the last reply, every request will use a new connection", self.address);
+                        self.keep_alive = false;
+                        // A challenge request changes nothing and a get can be run twice, any
+                        // other command may have been run before the connection was closed
+                        if leg == Leg::Challenge || base_command.command().is_idempotent() {
+                            continue;
+                        }
+                        log::error!(server:% = self.address, command; "{} closed the connection, {} is not sent again because it may have been run", self.address, command);
+                        return None;
+                    }
+                    log::error!(server:% = self.address, command; "{} didn't reply anything: {}", self.address, e);
+                    return None;
+                }
+            }
+        }
+    }
+}
+
+// The answer of a challenge must go through the connection that received the nonce
+#[derive(Clone, Copy, PartialEq)]
+enum Leg {
+    Only,
+    Challenge,
+    Response
+}
+
+fn exchange(transport: &mut dyn transport::Transport, pending: &mut Vec<u8>, request: &[u8]) -> io::Result<Vec<u8>> {
+    transport.send(request)?;
+    loop {
+        if let Some(reply) = transport::split_json(pending, transport::MAX_REPLY_SIZE) {
+            return Ok(reply);
+        }
+        match transport.receive() {
+            Ok(bytes) => pending.extend_from_slice(&bytes),
+            // Half a reply is still given back, so it is shown as unparsable and not as no answer
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof && !transport::is_blank(pending) => return Ok(mem::take(pending)),
+            Err(e) => return Err(e)
+        }
+    }
+}
+
+// The server closed the connection without replying. It could have done it before reading the
+// request (e.g. it closes the connections after every reply) or after running it, there is no way
+// to tell.
+fn is_closed_by_peer(e: &io::Error) -> bool {
+    matches!(e.kind(), io::ErrorKind::UnexpectedEof | io::ErrorKind::ConnectionReset |
+                       io::ErrorKind::ConnectionAborted | io::ErrorKind::BrokenPipe)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(transport: transport::MemoryTransport) -> Session {
+        Session::with_transport(String::from("admin"), ipparser::ServerAddress::new_from_str("127.0.0.1:7000").unwrap(),
+                                commands::ConnectionOptions::default(), Box::new(transport))
+    }
+
+    #[test]
+    fn replies_joined_in_one_read() {
+        let mut memory_transport = transport::MemoryTransport::new();
+        memory_transport.push_reply(b"{\"result\":\"ok\"} {\"result\":\"ok\",\"dropped_clients\":2}{\"result\"");
+        memory_transport.push_reply(b":\"ok\"}");
+        let mut session = session(memory_transport);
+        assert_eq!(session.send_and_interpret(commands::Command::Set(commands::Set::Capacity(30))), "result: ok");
+        let reply = session.send_and_parse(commands::Command::Set(commands::Set::DropVotes(2))).unwrap();
+        assert_eq!(reply["dropped_clients"], 2);
+        assert_eq!(session.send_and_interpret(commands::Command::Set(commands::Set::ListSize(3))), "result: ok");
+        assert!(session.is_connected());
+    }
+
+    // What comes before the connection is closed is still a reply, shown as unparsable
+    #[test]
+    fn half_a_reply() {
+        let mut memory_transport = transport::MemoryTransport::with_replies(&["{\"result\":"]);
+        memory_transport.push_error(io::ErrorKind::UnexpectedEof);
+        let mut session = session(memory_transport);
+        assert!(session.send_and_interpret(commands::Command::Set(commands::Set::Capacity(30))).starts_with("Could not parse the reply"));
+    }
+}
@@ -0,0 +1,369 @@
+// Author: Jorge Alarcon Alvarez
+// Email:  jorge4larcon@gmail.com
+// This module is used to simulate a MINT server, so admint can be tried without a real one.
+
+use crate::auth;
+use crate::clients;
+use crate::dispatcher;
+use crate::ipparser;
+use crate::server_config;
+use crate::transport;
+use serde::Deserialize;
+use std::collections;
+use std::fs;
+use std::io;
+use std::io::{
+    Read,
+    Write
+};
+use std::net;
+#[cfg(unix)]
+use std::os::unix::net as unix_net;
+use std::path;
+use std::sync;
+use std::thread;
+use std::time;
+
+// How to misbehave when replying a command, the faults are applied in the order of the fields
+#[derive(Deserialize, Default, Clone, Debug)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Fault {
+    // Send numbers and booleans as strings, e.g. "drop_votes": "3"
+    #[serde(default)]
+    pub wrong_types: bool,
+    // Pad the reply with a "padding" field until it has at least this many bytes
+    pub oversize: Option<usize>,
+    // Send at most this many clients in a list of clients, like a server that caps its pages
+    pub max_clients: Option<usize>,
+    // Send the reply with a trailing comma, so it is not JSON
+    #[serde(default)]
+    pub invalid_json: bool,
+    // Wait before replying
+    pub delay_ms: Option<u64>,
+    // Write the reply in chunks of this many bytes, waiting split_delay_ms between them
+    pub split: Option<usize>,
+    pub split_delay_ms: Option<u64>,
+    // Close the connection after writing this many bytes of the reply, after the whole reply when
+    // it is shorter
+    pub close_after: Option<usize>
+}
+
+// The faults of every command, read from a TOML file with a table per admint subcommand name,
+// "challenge" for the challenge requests or "*" for any command without its own table:
+//
+// [commands.running-config]
+// delay-ms = 2000
+//
+// [commands.set-dropvotes]
+// wrong-types = true
+// split = 8
+// split-delay-ms = 10
+#[derive(Deserialize, Default, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Scenario {
+    #[serde(default)]
+    pub commands: collections::BTreeMap<String, Fault>
+}
+
+impl Scenario {
+    pub fn load(path: &path::Path) -> Result<Scenario, String> {
+        match fs::read_to_string(path) {
+            Ok(contents) => Scenario::from_toml_str(&contents).map_err(|e| format!("{}: {}", path.display(), e)),
+            Err(e) => Err(format!("Could not read {}: {}", path.display(), e))
+        }
+    }
+
+    pub fn from_toml_str(contents: &str) -> Result<Scenario, String> {
+        toml::from_str::<Scenario>(contents).map_err(|e| e.to_string())
+    }
+
+    pub fn fault_for(&self, command: &str) -> Option<&Fault> {
+        self.commands.get(command).or_else(|| self.commands.get("*"))
+    }
+}
+
+// Everything the simulator knows, shared by the connections
+#[derive(Default)]
+pub struct SimState {
+    pub clients: clients::ClientsMap,
+    pub config: server_config::ServerConfig,
+    pub dispatcher: dispatcher::Dispatcher,
+    pub scenario: Scenario,
+    // Every request received, in order
+    pub requests: Vec<serde_json::Value>,
+    // The connections accepted
+    pub connections: usize
+}
+
+impl SimState {
+    pub fn new(clients: clients::ClientsMap, config: server_config::ServerConfig) -> SimState {
+        SimState { clients, config, ..SimState::default() }
+    }
+
+    pub fn with_scenario(mut self, scenario: Scenario) -> SimState {
+        self.scenario = scenario;
+        self
+    }
+
+    // Refuse the requests that are not signed (admint --sign)
+    pub fn with_required_signatures(mut self) -> SimState {
+        self.dispatcher.require_signatures = true;
+        self
+    }
+
+    pub fn reply(&mut self, request: &serde_json::Value, challenge: &mut auth::Challenge) -> String {
+        self.requests.push(request.clone());
+        self.dispatcher.dispatch(request, challenge, &mut self.clients, &mut self.config)
+    }
+}
+
+// The requests are small, one that is not complete after this many bytes is refused
+const MAX_REQUEST_SIZE: usize = transport::MAX_REPLY_SIZE;
+
+pub struct Simulator {
+    listener: Listener,
+    // Every TCP connection is served with TLS when it is set (admint --tls)
+    tls: Option<sync::Arc<rustls::ServerConfig>>,
+    state: sync::Arc<sync::Mutex<SimState>>
+}
+
+enum Listener {
+    Tcp(net::TcpListener),
+    #[cfg(unix)]
+    Unix(unix_net::UnixListener, path::PathBuf)
+}
+
+impl Simulator {
+    // IP:PORT or unix:PATH like the server addresses of admint. Use port 0 to get any free port,
+    // address says which one.
+    pub fn bind(address: &str, state: SimState) -> io::Result<Simulator> {
+        let listener = match address.strip_prefix(ipparser::UNIX_ADDRESS_PREFIX) {
+            #[cfg(unix)]
+            Some(socket_path) => Listener::Unix(unix_net::UnixListener::bind(socket_path)?, path::PathBuf::from(socket_path)),
+            #[cfg(not(unix))]
+            Some(_) => return Err(io::Error::new(io::ErrorKind::Unsupported, "Unix sockets are not supported in this platform")),
+            None => Listener::Tcp(net::TcpListener::bind(address)?)
+        };
+        Ok(Simulator { listener, tls: None, state: sync::Arc::new(sync::Mutex::new(state)) })
+    }
+
+    // See tls::server_config
+    pub fn with_tls(mut self, tls_config: sync::Arc<rustls::ServerConfig>) -> Simulator {
+        self.tls = Some(tls_config);
+        self
+    }
+
+    // The address to give to admint
+    pub fn address(&self) -> io::Result<String> {
+        match &self.listener {
+            Listener::Tcp(listener) => Ok(listener.local_addr()?.to_string()),
+            #[cfg(unix)]
+            Listener::Unix(_, socket_path) => Ok(format!("{}{}", ipparser::UNIX_ADDRESS_PREFIX, socket_path.display()))
+        }
+    }
+
+    pub fn state(&self) -> sync::Arc<sync::Mutex<SimState>> {
+        sync::Arc::clone(&self.state)
+    }
+
+    // Every connection gets its own thread and can carry many requests
+    pub fn serve(&self) {
+        match &self.listener {
+            Listener::Tcp(listener) => {
+                for stream in listener.incoming() {
+                    match stream {
+                        Ok(stream) => self.spawn_tcp_connection(stream),
+                        Err(e) => log::warn!("Could not accept a connection: {}", e)
+                    }
+                }
+            },
+            #[cfg(unix)]
+            Listener::Unix(listener, socket_path) => {
+                for stream in listener.incoming() {
+                    match stream {
+                        Ok(stream) => {
+                            let state = sync::Arc::clone(&self.state);
+                            let peer = format!("{}{}", ipparser::UNIX_ADDRESS_PREFIX, socket_path.display());
+                            thread::spawn(move || handle_connection(stream, peer, state));
+                        },
+                        Err(e) => log::warn!("Could not accept a connection: {}", e)
+                    }
+                }
+            }
+        }
+    }
+
+    fn spawn_tcp_connection(&self, stream: net::TcpStream) {
+        let state = sync::Arc::clone(&self.state);
+        let tls = self.tls.clone();
+        thread::spawn(move || {
+            let peer = stream.peer_addr().map(|peer| peer.to_string()).unwrap_or_default();
+            match tls {
+                Some(tls_config) => match rustls::ServerConnection::new(tls_config) {
+                    Ok(connection) => handle_connection(rustls::StreamOwned::new(connection, stream), peer, state),
+                    Err(e) => log::error!(peer; "{}", e)
+                },
+                None => handle_connection(stream, peer, state)
+            }
+        });
+    }
+
+    pub fn spawn(self) -> thread::JoinHandle<()> {
+        thread::spawn(move || self.serve())
+    }
+}
+
+// The requests are taken from the stream as they complete, many can come in one read or one can
+// be split in many
+fn handle_connection<S: Read + Write>(mut stream: S, peer: String, state: sync::Arc<sync::Mutex<SimState>>) {
+    log::info!(peer; "Connection from {}", peer);
+    match state.lock() {
+        Ok(mut state) => state.connections += 1,
+        Err(_) => return
+    }
+    let mut challenge = auth::Challenge::new();
+    let mut pending = Vec::new();
+    let mut buffer = vec![0; 4096];
+    loop {
+        let request = match transport::split_json(&mut pending, MAX_REQUEST_SIZE) {
+            Some(request) => request,
+            None => match stream.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(bytes_received) => {
+                    pending.extend_from_slice(&buffer[..bytes_received]);
+                    continue;
+                },
+                Err(e) => {
+                    log::debug!(peer; "{}", e);
+                    break;
+                }
+            }
+        };
+        let (reply, fault) = match serde_json::from_slice::<serde_json::Value>(&request) {
+            Ok(request) => {
+                log::debug!(peer; "request:\n{}", request);
+                match state.lock() {
+                    Ok(mut state) => {
+                        let fault = command_name(&request).and_then(|command| state.scenario.fault_for(command)).cloned();
+                        (state.reply(&request, &mut challenge), fault)
+                    },
+                    Err(_) => return
+                }
+            },
+            Err(e) => {
+                let reply = dispatcher::error_reply(dispatcher::BAD_REQUEST, &format!("the request is not valid JSON: {}", e));
+                let _ = stream.write_all(reply.as_bytes());
+                let _ = stream.flush();
+                break;
+            }
+        };
+        log::debug!(peer; "reply:\n{}", reply);
+        match write_reply(&mut stream, reply, fault.as_ref()) {
+            Ok(true) => {},
+            Ok(false) => {
+                log::info!(peer; "Closing the connection in the middle of the reply");
+                break;
+            },
+            Err(e) => {
+                log::warn!(peer; "The reply could not be sent: {}", e);
+                break;
+            }
+        }
+    }
+    log::info!(peer; "{} disconnected", peer);
+}
+
+// false when the fault closes the connection
+fn write_reply<W: Write>(writer: &mut W, reply: String, fault: Option<&Fault>) -> io::Result<bool> {
+    let fault = match fault {
+        Some(fault) => fault,
+        None => {
+            writer.write_all(reply.as_bytes())?;
+            writer.flush()?;
+            return Ok(true);
+        }
+    };
+    let mut reply = reply;
+    if let Some(max_clients) = fault.max_clients {
+        if let Ok(mut value) = serde_json::from_str::<serde_json::Value>(&reply) {
+            if let Some(clients) = value.get_mut("clients").and_then(|clients| clients.as_array_mut()) {
+                clients.truncate(max_clients);
+            }
+            reply = value.to_string();
+        }
+    }
+    if fault.wrong_types {
+        if let Ok(mut value) = serde_json::from_str::<serde_json::Value>(&reply) {
+            stringify_scalars(&mut value);
+            reply = value.to_string();
+        }
+    }
+    if let Some(size) = fault.oversize {
+        if let Ok(mut value) = serde_json::from_str::<serde_json::Value>(&reply) {
+            if let Some(fields) = value.as_object_mut() {
+                fields.insert(String::from("padding"), serde_json::Value::from(""));
+                let padding = size.saturating_sub(serde_json::Value::Object(fields.clone()).to_string().len());
+                fields.insert(String::from("padding"), serde_json::Value::from("x".repeat(padding)));
+            }
+            reply = value.to_string();
+        }
+    }
+    if fault.invalid_json {
+        reply = format!("{},}}", reply.trim_end_matches('}'));
+    }
+    if let Some(delay_ms) = fault.delay_ms {
+        thread::sleep(time::Duration::from_millis(delay_ms));
+    }
+    let mut bytes = reply.as_bytes();
+    let mut keep_open = true;
+    if let Some(close_after) = fault.close_after {
+        bytes = &bytes[..close_after.min(bytes.len())];
+        keep_open = false;
+    }
+    let chunk_size = fault.split.unwrap_or(bytes.len()).max(1);
+    for (i, chunk) in bytes.chunks(chunk_size).enumerate() {
+        if i > 0 {
+            if let Some(split_delay_ms) = fault.split_delay_ms {
+                thread::sleep(time::Duration::from_millis(split_delay_ms));
+            }
+        }
+        writer.write_all(chunk)?;
+        writer.flush()?;
+    }
+    Ok(keep_open)
+}
+
+fn stringify_scalars(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Object(fields) => fields.values_mut().for_each(stringify_scalars),
+        serde_json::Value::Array(values) => values.iter_mut().for_each(stringify_scalars),
+        serde_json::Value::Number(_) | serde_json::Value::Bool(_) => *value = serde_json::Value::from(value.to_string()),
+        _ => {}
+    }
+}
+
+// The admint subcommand that sent the request, the faults are chosen by it
+pub fn command_name(request: &serde_json::Value) -> Option<&'static str> {
+    if auth::Challenge::is_challenge_request(request) {
+        return Some("challenge");
+    }
+    if auth::Challenge::is_signed_request(request) {
+        let inner = serde_json::from_str::<serde_json::Value>(request.get("request")?.as_str()?).ok()?;
+        return command_name(&inner);
+    }
+    let field = |name: &str| request.get(name).and_then(|value| value.as_str());
+    match (field("method")?, field("how").or_else(|| field("what"))) {
+        ("get", Some("running_configuration")) => Some("running-config"),
+        ("get", Some("index")) => Some("get-index"),
+        ("get", Some("mac")) => Some("get-mac"),
+        ("get", Some("username")) => Some("get-username"),
+        ("set", Some("capacity")) => Some("set-capacity"),
+        ("set", Some("list_size")) => Some("set-listsize"),
+        ("set", Some("drop_votes")) => Some("set-dropvotes"),
+        ("set", Some("drop_verification")) => Some("set-dropverification"),
+        ("set", Some("key")) => Some("set-key"),
+        ("set", Some("password")) => Some("set-password"),
+        ("drop", _) => Some("drop"),
+        _ => None
+    }
+}
@@ -0,0 +1,148 @@
+// Author: Jorge Alarcon Alvarez
+// Email:  jorge4larcon@gmail.com
+// This module is used to find the servers a subcommand acts on, for the subcommands that can act
+// on many servers at once.
+
+extern crate clap;
+
+use crate::clients;
+use crate::commands;
+use crate::config;
+use crate::ipparser;
+use crate::server_config;
+use crate::session;
+
+// How many clients are asked for in every get-index
+const CLIENTS_PAGE_SIZE: usize = 100;
+
+// A server and how to talk with it
+pub struct Target {
+    // The profile name or the address
+    pub name: String,
+    pub password: String,
+    pub address: ipparser::ServerAddress,
+    pub options: commands::ConnectionOptions,
+    // The profile it was read from, None for the ones given by address
+    pub profile: Option<String>
+}
+
+impl Target {
+    // The server of a single command, named by its address
+    pub fn from_base_command(command: &commands::BaseCommand) -> Target {
+        Target {
+            name: command.address().to_string(),
+            password: command.password().to_string(),
+            address: command.address().clone(),
+            options: command.options().clone(),
+            profile: None
+        }
+    }
+
+    pub fn command(&self, command: commands::Command) -> commands::BaseCommand {
+        commands::BaseCommand::new(self.password.clone(), self.address.clone(), command, self.options.clone())
+    }
+
+    // The same server with another admin password
+    pub fn with_password(&self, password: &str) -> Target {
+        Target {
+            name: self.name.clone(),
+            password: password.to_string(),
+            address: self.address.clone(),
+            options: self.options.clone(),
+            profile: self.profile.clone()
+        }
+    }
+
+    // Many commands through the same connection
+    pub fn session(&self) -> session::Session {
+        session::Session::new(self.password.clone(), self.address.clone(), self.options.clone())
+    }
+
+    // Every client, asking for them a page at a time through the same connection until a page comes
+    // empty. A server can send less clients than asked for, the next page starts after them.
+    pub fn clients(&self) -> Result<clients::ClientsMap, String> {
+        let mut clients_map = clients::ClientsMap::new();
+        let mut session = self.session();
+        let mut start_index = 0;
+        loop {
+            let end_index = start_index + CLIENTS_PAGE_SIZE;
+            let reply = session.send_and_parse(commands::Command::Get(commands::Get::Index { start_index, end_index }))?;
+            let page = reply.get("clients").and_then(|clients| clients.as_array()).ok_or(format!("{} did not send the clients", self.name))?;
+            let mut repeated = 0;
+            for value in page {
+                let mac = value.get("mac").and_then(|mac| mac.as_str()).and_then(ipparser::MacAddress::new_from_str);
+                match (mac, clients::Client::from_json_value(value)) {
+                    (Some(mac), Some(_)) if clients_map.exists_by_mac(&mac) => repeated += 1,
+                    (Some(mac), Some(client)) => {
+                        clients_map.insert(&mac, &client);
+                    },
+                    _ => log::warn!(server = self.name.as_str(); "Skipping a client that is not valid: {}", value)
+                }
+            }
+            if page.is_empty() {
+                return Ok(clients_map);
+            }
+            // A server that does not skip the first start_index clients would never send an empty page
+            if repeated == page.len() {
+                return Err(format!("{} sent the clients of index {} again", self.name, start_index));
+            }
+            start_index += page.len();
+        }
+    }
+
+    pub fn running_config(&self) -> Result<server_config::ServerConfig, String> {
+        let command = self.command(commands::Command::Get(commands::Get::RunningConfiguration {
+            setting: None,
+            format: server_config::ConfigFormat::Text
+        }));
+        let reply = command.send_and_parse()?;
+        server_config::ServerConfig::from_reply(&reply).map_err(|e| format!("The running config of {} is not valid: {}", self.name, e))
+    }
+}
+
+// Every SERVER is a profile name or an address, the addresses use the password given with
+// --admin-password. With --group every profile of the group is added, sorted by name.
+pub fn from_clap_matches(matches: &clap::ArgMatches) -> Result<Vec<Target>, String> {
+    let subcommand_matches = match matches.subcommand() {
+        (_name, Some(subcommand_matches)) => subcommand_matches,
+        _ => return Err(String::from("There is no subcommand"))
+    };
+    let profiles = config::Profiles::from_clap_matches(matches)?;
+    let mut targets = Vec::new();
+    for server in subcommand_matches.values_of("server").into_iter().flatten() {
+        match profiles.get(server) {
+            Some(profile) => targets.push(from_profile(matches, server, profile)?),
+            None => {
+                let address = ipparser::ServerAddress::new_from_str(server)
+                    .ok_or(format!("{} is neither a profile nor a valid server address", server))?;
+                let password = subcommand_matches.value_of("admin-password")
+                    .ok_or(format!("The admin password of {} is required, give it with --admin-password or use a profile", server))?;
+                let options = commands::ConnectionOptions::from_clap_matches(matches, None)
+                    .ok_or("I didn't understand your connection options")?;
+                targets.push(Target { name: server.to_string(), password: password.to_string(), address, options, profile: None });
+            }
+        }
+    }
+    if let Some(group) = subcommand_matches.value_of("group") {
+        let group_profiles = profiles.in_group(group);
+        if group_profiles.is_empty() {
+            return Err(format!("There are no profiles in the group {}", group));
+        }
+        for (name, profile) in group_profiles {
+            if !targets.iter().any(|target| &target.name == name) {
+                targets.push(from_profile(matches, name, profile)?);
+            }
+        }
+    }
+    Ok(targets)
+}
+
+fn from_profile(matches: &clap::ArgMatches, name: &str, profile: &config::Profile) -> Result<Target, String> {
+    let address = profile.server_address.as_deref().and_then(ipparser::ServerAddress::new_from_str)
+        .ok_or(format!("The profile {} has no valid server-address", name))?;
+    let password = profile.admin_password.as_deref()
+        .ok_or(format!("The profile {} has no admin-password", name))?;
+    let options = commands::ConnectionOptions::from_clap_matches(matches, Some(profile))
+        .ok_or(format!("I didn't understand the connection options of the profile {}", name))?;
+    Ok(Target { name: name.to_string(), password: password.to_string(), address, options, profile: Some(name.to_string()) })
+}
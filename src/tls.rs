@@ -0,0 +1,177 @@
+// Author: Jorge Alarcon Alvarez
+// Email:  jorge4larcon@gmail.com
+// This module is used to protect the admin channel with TLS.
+
+extern crate rustls;
+extern crate rustls_pemfile;
+extern crate sha2;
+extern crate webpki_roots;
+
+use sha2::Digest;
+use std::convert::TryFrom;
+use std::fs;
+use std::io;
+use std::net;
+use std::path;
+use std::sync;
+use std::time;
+
+pub type TlsStream = rustls::StreamOwned<rustls::ClientConnection, net::TcpStream>;
+
+#[derive(Clone, Default, Debug)]
+pub struct TlsOptions {
+    // PEM file with the CAs that can sign the server certificate, the Mozilla roots by default
+    pub ca_file: Option<path::PathBuf>,
+    // PEM files with the certificate chain and the private key used to authenticate this client
+    pub client_cert: Option<path::PathBuf>,
+    pub client_key: Option<path::PathBuf>,
+    // SHA-256 of the server certificate, when it is set the certificate is trusted only if it
+    // matches, no matter who signed it (so self-signed certificates can be used). Its name and
+    // expiry are not checked either, see PinnedCertVerifier
+    pub fingerprint: Option<[u8; 32]>,
+    // The name checked against the server certificate, the host of the server address by default
+    pub server_name: Option<String>
+}
+
+impl TlsOptions {
+    pub fn client_config(&self) -> Result<sync::Arc<rustls::ClientConfig>, String> {
+        let mut root_store = rustls::RootCertStore::empty();
+        if let Some(ca_file) = &self.ca_file {
+            for cert in read_certs(ca_file)? {
+                root_store.add(&cert).map_err(|e| format!("{}: {}", ca_file.display(), e))?;
+            }
+        } else {
+            root_store.add_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.iter().map(|ta| {
+                rustls::OwnedTrustAnchor::from_subject_spki_name_constraints(ta.subject, ta.spki, ta.name_constraints)
+            }));
+        }
+        let builder = rustls::ClientConfig::builder().with_safe_defaults().with_root_certificates(root_store);
+        let mut config = match (&self.client_cert, &self.client_key) {
+            (Some(cert_file), Some(key_file)) => {
+                builder.with_client_auth_cert(read_certs(cert_file)?, read_private_key(key_file)?)
+                       .map_err(|e| format!("{}: {}", cert_file.display(), e))?
+            },
+            (None, None) => builder.with_no_client_auth(),
+            _ => return Err(String::from("The client certificate and the client key must be given together"))
+        };
+        if let Some(fingerprint) = self.fingerprint {
+            config.dangerous().set_certificate_verifier(sync::Arc::new(PinnedCertVerifier { fingerprint }));
+        }
+        Ok(sync::Arc::new(config))
+    }
+
+    // Wraps an established connection and completes the handshake, so a bad certificate is
+    // reported here and not when the request is sent.
+    pub fn connect(&self, tcp_stream: net::TcpStream, host: &str) -> Result<TlsStream, String> {
+        let server_name = self.server_name(host)?;
+        let connection = rustls::ClientConnection::new(self.client_config()?, server_name).map_err(|e| e.to_string())?;
+        let mut tls_stream = rustls::StreamOwned::new(connection, tcp_stream);
+        while tls_stream.conn.is_handshaking() {
+            tls_stream.conn.complete_io(&mut tls_stream.sock).map_err(|e| format!("TLS handshake with {} failed: {}", self.name(host), e))?;
+        }
+        log_session(self.name(host), tls_stream.conn.peer_certificates());
+        Ok(tls_stream)
+    }
+
+    pub fn server_name(&self, host: &str) -> Result<rustls::ServerName, String> {
+        let name = self.name(host);
+        rustls::ServerName::try_from(name).map_err(|e| format!("{} is not a valid TLS server name: {}", name, e))
+    }
+
+    fn name<'a>(&'a self, host: &'a str) -> &'a str {
+        self.server_name.as_deref().unwrap_or(host)
+    }
+}
+
+pub(crate) fn log_session(name: &str, peer_certificates: Option<&[rustls::Certificate]>) {
+    if let Some(cert) = peer_certificates.and_then(|certs| certs.first()) {
+        log::info!("TLS session established with {}, certificate SHA-256 {}", name, fingerprint_to_string(&fingerprint(&cert.0)));
+    }
+}
+
+// A certificate and private key to serve the admin channel with TLS
+pub fn server_config(cert_file: &path::Path, key_file: &path::Path) -> Result<sync::Arc<rustls::ServerConfig>, String> {
+    let config = rustls::ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(read_certs(cert_file)?, read_private_key(key_file)?)
+        .map_err(|e| format!("{}: {}", cert_file.display(), e))?;
+    Ok(sync::Arc::new(config))
+}
+
+pub fn fingerprint(cert_der: &[u8]) -> [u8; 32] {
+    let mut fingerprint = [0; 32];
+    fingerprint.copy_from_slice(&sha2::Sha256::digest(cert_der));
+    fingerprint
+}
+
+// AB:CD:EF:...
+pub fn fingerprint_to_string(fingerprint: &[u8; 32]) -> String {
+    fingerprint.iter().map(|byte| format!("{:02X}", byte)).collect::<Vec<String>>().join(":")
+}
+
+// Accepts 64 hex digits, optionally separated by `:`, and an optional `sha256:` prefix
+pub fn str_to_fingerprint(fingerprint: &str) -> Option<[u8; 32]> {
+    let hex: String = fingerprint.trim_start_matches("sha256:").chars().filter(|c| *c != ':').collect();
+    if hex.len() != 64 || !hex.is_ascii() {
+        return None;
+    }
+    let mut bytes = [0; 32];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).ok()?;
+    }
+    Some(bytes)
+}
+
+pub fn is_fingerprint(fingerprint: &str) -> bool {
+    str_to_fingerprint(fingerprint).is_some()
+}
+
+fn read_certs(file: &path::Path) -> Result<Vec<rustls::Certificate>, String> {
+    let f = fs::File::open(file).map_err(|e| format!("Could not open {}: {}", file.display(), e))?;
+    let certs = rustls_pemfile::certs(&mut io::BufReader::new(f)).map_err(|e| format!("{}: {}", file.display(), e))?;
+    if certs.is_empty() {
+        return Err(format!("There are no certificates in {}", file.display()));
+    }
+    Ok(certs.into_iter().map(rustls::Certificate).collect())
+}
+
+fn read_private_key(file: &path::Path) -> Result<rustls::PrivateKey, String> {
+    let f = fs::File::open(file).map_err(|e| format!("Could not open {}: {}", file.display(), e))?;
+    let mut reader = io::BufReader::new(f);
+    loop {
+        match rustls_pemfile::read_one(&mut reader).map_err(|e| format!("{}: {}", file.display(), e))? {
+            Some(rustls_pemfile::Item::PKCS8Key(key)) |
+            Some(rustls_pemfile::Item::RSAKey(key)) |
+            Some(rustls_pemfile::Item::ECKey(key)) => return Ok(rustls::PrivateKey(key)),
+            Some(_) => {},
+            None => return Err(format!("There is no private key in {}", file.display()))
+        }
+    }
+}
+
+// Trusts the one certificate with the pinned SHA-256 and nothing else. The fingerprint is all that
+// is checked: not who signed it, not the server name and not the validity dates, so a pinned
+// certificate keeps being accepted after it expires and whatever the address used to reach it.
+// Replacing the certificate requires pinning the new one.
+struct PinnedCertVerifier {
+    fingerprint: [u8; 32]
+}
+
+impl rustls::client::ServerCertVerifier for PinnedCertVerifier {
+    fn verify_server_cert(&self,
+                          end_entity: &rustls::Certificate,
+                          _intermediates: &[rustls::Certificate],
+                          _server_name: &rustls::ServerName,
+                          _scts: &mut dyn Iterator<Item = &[u8]>,
+                          _ocsp_response: &[u8],
+                          _now: time::SystemTime) -> Result<rustls::client::ServerCertVerified, rustls::Error> {
+        let received = fingerprint(&end_entity.0);
+        if received == self.fingerprint {
+            Ok(rustls::client::ServerCertVerified::assertion())
+        } else {
+            Err(rustls::Error::General(format!("the server certificate SHA-256 is {}, it was expected to be {}",
+                                               fingerprint_to_string(&received), fingerprint_to_string(&self.fingerprint))))
+        }
+    }
+}
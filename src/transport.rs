@@ -0,0 +1,264 @@
+// Author: Jorge Alarcon Alvarez
+// Email:  jorge4larcon@gmail.com
+// This module is used to carry the requests to the server and its replies back.
+
+use crate::tls;
+use std::collections;
+use std::io;
+use std::io::{
+    Read,
+    Write
+};
+use std::mem;
+use std::net;
+#[cfg(unix)]
+use std::os::unix::net as unix_net;
+#[cfg(unix)]
+use std::path;
+use std::time;
+
+pub const MAX_REPLY_SIZE: usize = 65535;
+const READ_SIZE: usize = 4096;
+
+pub trait Transport {
+    fn send(&mut self, request: &[u8]) -> io::Result<()>;
+    fn receive(&mut self) -> io::Result<Vec<u8>>;
+}
+
+// Any connected byte stream, every request is written whole and the reply is read until it is a
+// complete JSON value, however the network split it.
+pub struct StreamTransport<S: Read + Write> {
+    stream: S,
+    // Bytes received after the end of the last reply
+    pending: Vec<u8>
+}
+
+pub type TcpTransport = StreamTransport<net::TcpStream>;
+pub type TlsTransport = StreamTransport<tls::TlsStream>;
+#[cfg(unix)]
+pub type UnixTransport = StreamTransport<unix_net::UnixStream>;
+
+impl<S: Read + Write> StreamTransport<S> {
+    pub fn new(stream: S) -> StreamTransport<S> {
+        StreamTransport { stream, pending: Vec::new() }
+    }
+
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+}
+
+impl<S: Read + Write> Transport for StreamTransport<S> {
+    fn send(&mut self, request: &[u8]) -> io::Result<()> {
+        self.stream.write_all(request)?;
+        self.stream.flush()
+    }
+
+    // A reply that is not JSON is given back once the connection is closed or MAX_REPLY_SIZE bytes
+    // were received, so it is shown as unparsable and not as no reply
+    fn receive(&mut self) -> io::Result<Vec<u8>> {
+        let mut buffer = vec![0; READ_SIZE];
+        loop {
+            if let Some(reply) = split_json(&mut self.pending, MAX_REPLY_SIZE) {
+                return Ok(reply);
+            }
+            match self.stream.read(&mut buffer)? {
+                0 if is_blank(&self.pending) => {
+                    self.pending.clear();
+                    return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "the connection was closed without a reply"));
+                },
+                0 => return Ok(mem::take(&mut self.pending)),
+                bytes_received => self.pending.extend_from_slice(&buffer[..bytes_received])
+            }
+        }
+    }
+}
+
+impl StreamTransport<net::TcpStream> {
+    // `timeout` is applied to every read and write, without `connect_timeout` the OS decides
+    pub fn connect(address: &net::SocketAddr, connect_timeout: Option<time::Duration>, timeout: time::Duration) -> io::Result<TcpTransport> {
+        let stream = match connect_timeout {
+            Some(connect_timeout) => net::TcpStream::connect_timeout(address, connect_timeout)?,
+            None => net::TcpStream::connect(address)?
+        };
+        stream.set_read_timeout(Some(timeout))?;
+        stream.set_write_timeout(Some(timeout))?;
+        Ok(StreamTransport::new(stream))
+    }
+}
+
+impl StreamTransport<tls::TlsStream> {
+    pub fn connect(tcp_transport: TcpTransport, tls_options: &tls::TlsOptions, host: &str) -> Result<TlsTransport, String> {
+        Ok(StreamTransport::new(tls_options.connect(tcp_transport.into_inner(), host)?))
+    }
+}
+
+#[cfg(unix)]
+impl StreamTransport<unix_net::UnixStream> {
+    pub fn connect(path: &path::Path, timeout: time::Duration) -> io::Result<UnixTransport> {
+        let stream = unix_net::UnixStream::connect(path)?;
+        stream.set_read_timeout(Some(timeout))?;
+        stream.set_write_timeout(Some(timeout))?;
+        Ok(StreamTransport::new(stream))
+    }
+}
+
+// Takes the first JSON value out of `pending`, or everything received when it is not JSON or it is
+// still incomplete after `max_size` bytes. None while the value is incomplete.
+pub fn split_json(pending: &mut Vec<u8>, max_size: usize) -> Option<Vec<u8>> {
+    let mut values = serde_json::Deserializer::from_slice(pending).into_iter::<serde_json::Value>();
+    match values.next() {
+        Some(Ok(_)) => {
+            let end = values.byte_offset();
+            let rest = pending.split_off(end);
+            Some(mem::replace(pending, rest))
+        },
+        Some(Err(e)) if e.is_eof() && pending.len() < max_size => None,
+        Some(Err(_)) => Some(mem::take(pending)),
+        None => None
+    }
+}
+
+pub fn is_blank(bytes: &[u8]) -> bool {
+    bytes.iter().all(u8::is_ascii_whitespace)
+}
+
+// Replays a script of replies and keeps every request it is given, so the commands can be run
+// without a server.
+#[derive(Default)]
+pub struct MemoryTransport {
+    replies: collections::VecDeque<io::Result<Vec<u8>>>,
+    requests: Vec<Vec<u8>>
+}
+
+impl MemoryTransport {
+    pub fn new() -> MemoryTransport {
+        MemoryTransport::default()
+    }
+
+    pub fn with_replies(replies: &[&str]) -> MemoryTransport {
+        let mut memory_transport = MemoryTransport::new();
+        for reply in replies {
+            memory_transport.push_reply(reply.as_bytes());
+        }
+        memory_transport
+    }
+
+    pub fn push_reply(&mut self, reply: &[u8]) {
+        self.replies.push_back(Ok(reply.to_vec()));
+    }
+
+    // The next receive fails with this error, e.g. io::ErrorKind::TimedOut
+    pub fn push_error(&mut self, kind: io::ErrorKind) {
+        self.replies.push_back(Err(io::Error::from(kind)));
+    }
+
+    pub fn requests(&self) -> &[Vec<u8>] {
+        &self.requests
+    }
+
+    pub fn pending_replies(&self) -> usize {
+        self.replies.len()
+    }
+}
+
+impl Transport for MemoryTransport {
+    fn send(&mut self, request: &[u8]) -> io::Result<()> {
+        self.requests.push(request.to_vec());
+        Ok(())
+    }
+
+    fn receive(&mut self) -> io::Result<Vec<u8>> {
+        match self.replies.pop_front() {
+            Some(reply) => reply,
+            None => Err(io::Error::new(io::ErrorKind::UnexpectedEof, "there are no more scripted replies"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands;
+    use crate::ipparser;
+
+    #[test]
+    fn memory_transport_replays_the_script() {
+        let mut memory_transport = MemoryTransport::with_replies(&["{\"result\":\"ok\"}"]);
+        memory_transport.push_error(io::ErrorKind::TimedOut);
+        memory_transport.push_reply(b"{\"error\":2}");
+        assert_eq!(memory_transport.pending_replies(), 3);
+        memory_transport.send(b"first").unwrap();
+        assert_eq!(memory_transport.receive().unwrap(), b"{\"result\":\"ok\"}");
+        memory_transport.send(b"second").unwrap();
+        assert_eq!(memory_transport.receive().unwrap_err().kind(), io::ErrorKind::TimedOut);
+        assert_eq!(memory_transport.receive().unwrap(), b"{\"error\":2}");
+        assert_eq!(memory_transport.receive().unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+        assert_eq!(memory_transport.pending_replies(), 0);
+        assert_eq!(memory_transport.requests(), [b"first".to_vec(), b"second".to_vec()]);
+    }
+
+    // Returns a chunk per read, like the network can
+    struct ChunkedStream {
+        chunks: collections::VecDeque<Vec<u8>>
+    }
+
+    impl Read for ChunkedStream {
+        fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+            match self.chunks.pop_front() {
+                Some(chunk) => {
+                    buffer[..chunk.len()].copy_from_slice(&chunk);
+                    Ok(chunk.len())
+                },
+                None => Ok(0)
+            }
+        }
+    }
+
+    impl Write for ChunkedStream {
+        fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+            Ok(bytes.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn stream_transport(chunks: &[&[u8]]) -> StreamTransport<ChunkedStream> {
+        StreamTransport::new(ChunkedStream { chunks: chunks.iter().map(|chunk| chunk.to_vec()).collect() })
+    }
+
+    #[test]
+    fn stream_transport_receives_whole_replies() {
+        let mut transport = stream_transport(&[b"{\"result\":", b"\"ok\"} {\"error\":2}", b" {\"a\":[1,", b"2]}"]);
+        assert_eq!(transport.receive().unwrap(), b"{\"result\":\"ok\"}");
+        assert_eq!(transport.receive().unwrap(), b" {\"error\":2}");
+        assert_eq!(transport.receive().unwrap(), b" {\"a\":[1,2]}");
+        assert_eq!(transport.receive().unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn stream_transport_gives_back_what_is_not_json() {
+        assert_eq!(stream_transport(&[b"{\"result\":\"ok\",}"]).receive().unwrap(), b"{\"result\":\"ok\",}");
+        assert_eq!(stream_transport(&[b"{\"result\":"]).receive().unwrap(), b"{\"result\":");
+        assert_eq!(stream_transport(&[b" \n"]).receive().unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+        // A string that never ends is read only up to the limit
+        let chunk = vec![b'a'; READ_SIZE];
+        let mut chunks: Vec<&[u8]> = vec![b"{\"result\":\""];
+        chunks.extend((0..MAX_REPLY_SIZE / READ_SIZE + 2).map(|_| chunk.as_slice()));
+        let reply = stream_transport(&chunks).receive().unwrap();
+        assert!(reply.len() >= MAX_REPLY_SIZE && reply.len() < MAX_REPLY_SIZE + READ_SIZE);
+    }
+
+    // The commands run through it like through a connection
+    #[test]
+    fn memory_transport_runs_commands() {
+        let base_command = commands::BaseCommand::new(String::from("admin"), ipparser::ServerAddress::new_from_str("127.0.0.1:7000").unwrap(),
+                                                      commands::Command::Set(commands::Set::Capacity(30)), commands::ConnectionOptions::default());
+        let mut memory_transport = MemoryTransport::with_replies(&["{\"result\":\"ok\"}"]);
+        assert_eq!(base_command.send_and_interpret_with(&mut memory_transport), "result: ok");
+        let request = serde_json::from_slice::<serde_json::Value>(&memory_transport.requests()[0]).unwrap();
+        assert_eq!((&request["method"], &request["what"], &request["capacity"]), (&serde_json::json!("set"), &serde_json::json!("capacity"), &serde_json::json!(30)));
+    }
+}
@@ -0,0 +1,67 @@
+// Author: Jorge Alarcon Alvarez
+// Email:  jorge4larcon@gmail.com
+// This module is used to run the async client against the simulator, through every kind of
+// connection it opens.
+
+#![cfg(feature = "async")]
+
+extern crate admint;
+extern crate tokio;
+
+mod common;
+
+use common::TestServer;
+
+fn runtime() -> tokio::runtime::Runtime {
+    tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap()
+}
+
+#[test]
+fn many_servers_at_once() {
+    let runtime = runtime();
+    let servers: Vec<TestServer> = (0..3).map(|_| TestServer::new()).collect();
+    let handles: Vec<_> = servers.iter().enumerate()
+        .map(|(i, server)| {
+            let command = server.command(&["set-capacity", &(40 + i).to_string()]);
+            runtime.spawn(async move { command.send_and_interpret_async().await })
+        })
+        .collect();
+    for (i, handle) in handles.into_iter().enumerate() {
+        assert_eq!(runtime.block_on(handle).unwrap(), "result: ok");
+        assert_eq!(servers[i].state.lock().unwrap().config.capacity() as usize, 40 + i);
+    }
+}
+
+#[test]
+fn the_same_reply_as_the_blocking_client() {
+    let runtime = runtime();
+    let server = TestServer::new();
+    let output = runtime.block_on(server.command(&["get-index", "0", "5"]).send_and_interpret_async());
+    assert_eq!(output, server.run(&["get-index", "0", "5"], &[]));
+    let output = runtime.block_on(server.command_with(&["running-config"], &["--auth", "challenge"]).send_and_interpret_async());
+    assert_eq!(output, server.run(&["running-config"], &[]));
+}
+
+#[test]
+fn tls() {
+    let runtime = runtime();
+    let server = TestServer::with_tls();
+    let command = server.command_with(&["set-listsize", "4"], &["--tls-ca-file", &common::tls_file("ca.pem")]);
+    assert_eq!(runtime.block_on(command.send_and_interpret_async()), "result: ok");
+    assert_eq!(server.state.lock().unwrap().config.list_size(), 4);
+    let command = server.command_with(&["set-listsize", "5"], &["--tls"]);
+    assert_ne!(runtime.block_on(command.send_and_interpret_async()), "result: ok");
+    assert_eq!(server.state.lock().unwrap().config.list_size(), 4);
+}
+
+#[cfg(unix)]
+#[test]
+fn unix_socket() {
+    let runtime = runtime();
+    let socket_path = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join(format!("mint-async-{}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&socket_path);
+    let simulator = admint::simulator::Simulator::bind(&format!("unix:{}", socket_path.display()), common::default_state(TestServer::clients())).unwrap();
+    let server = TestServer::serve(simulator);
+    assert_eq!(runtime.block_on(server.command(&["get-index", "0", "3"]).send_and_interpret_async()), server.run(&["get-index", "0", "3"], &[]));
+    let _ = std::fs::remove_file(&socket_path);
+}
@@ -0,0 +1,118 @@
+// Author: Jorge Alarcon Alvarez
+// Email:  jorge4larcon@gmail.com
+// This module is used by the integration tests to start simulators and run admint against them.
+
+// Every test file uses a different part of it
+#![allow(dead_code)]
+
+use admint::cli;
+use admint::clients;
+use admint::commands;
+use admint::ipparser;
+use admint::seed;
+use admint::server_config;
+use admint::simulator;
+use admint::tls;
+use std::fs;
+use std::path;
+use std::sync;
+
+pub const KEY: &str = "admin";
+
+pub struct TestServer {
+    pub address: String,
+    pub state: sync::Arc<sync::Mutex<simulator::SimState>>
+}
+
+impl TestServer {
+    // The same 12 clients every time, the golden files depend on them
+    pub fn clients() -> clients::ClientsMap {
+        let seed_options = seed::SeedOptions {
+            count: 12,
+            seed: 40,
+            subnets: vec![seed::Subnet::new_from_str("10.0.0.0/24").unwrap()],
+            max_drop_votes: 2,
+            ..seed::SeedOptions::default()
+        };
+        seed::generate(&seed_options).unwrap()
+    }
+
+    pub fn start<F: FnOnce(clients::ClientsMap) -> simulator::SimState>(state: F) -> TestServer {
+        TestServer::serve(simulator::Simulator::bind("127.0.0.1:0", state(TestServer::clients())).unwrap())
+    }
+
+    pub fn serve(simulator: simulator::Simulator) -> TestServer {
+        let address = simulator.address().unwrap();
+        let state = simulator.state();
+        simulator.spawn();
+        TestServer { address, state }
+    }
+
+    pub fn new() -> TestServer {
+        TestServer::start(default_state)
+    }
+
+    // Serves tests/tls/server.pem, see tls_file
+    pub fn with_tls() -> TestServer {
+        let tls_config = tls::server_config(path::Path::new(&tls_file("server.pem")), path::Path::new(&tls_file("server.key"))).unwrap();
+        let simulator = simulator::Simulator::bind("127.0.0.1:0", default_state(TestServer::clients())).unwrap();
+        TestServer::serve(simulator.with_tls(tls_config))
+    }
+
+    // Injects the faults of a scenario, e.g. "[commands.set-key]\nclose-after = 0\n"
+    pub fn with_scenario(scenario: &str) -> TestServer {
+        let scenario = simulator::Scenario::from_toml_str(scenario).unwrap();
+        TestServer::start(|clients_map| default_state(clients_map).with_scenario(scenario))
+    }
+
+    // Parses the arguments like the binary, the admin password and the address go at the end
+    pub fn run(&self, args: &[&str], options: &[&str]) -> String {
+        self.command_with(args, options).send_and_interpret()
+    }
+
+    pub fn command(&self, args: &[&str]) -> commands::BaseCommand {
+        self.command_with(args, &[])
+    }
+
+    pub fn command_with(&self, args: &[&str], options: &[&str]) -> commands::BaseCommand {
+        command_at(&self.address, args, options)
+    }
+
+    pub fn requests(&self) -> Vec<serde_json::Value> {
+        self.state.lock().unwrap().requests.clone()
+    }
+
+    pub fn first_client(&self) -> (ipparser::MacAddress, clients::Client) {
+        self.state.lock().unwrap().clients.range(0, 1).remove(0)
+    }
+}
+
+// The command for a server that is not a TestServer
+pub fn command_at(address: &str, args: &[&str], options: &[&str]) -> commands::BaseCommand {
+    let mut argv = vec!["admint"];
+    argv.extend_from_slice(options);
+    argv.extend_from_slice(args);
+    argv.push(KEY);
+    argv.push(address);
+    commands::BaseCommand::from_clap_matches(&matches(&argv), None).unwrap()
+}
+
+pub fn default_state(clients_map: clients::ClientsMap) -> simulator::SimState {
+    simulator::SimState::new(clients_map, server_config::ServerConfig::default())
+}
+
+pub fn matches(argv: &[&str]) -> clap::ArgMatches<'static> {
+    cli::build_app().get_matches_from_safe(argv.to_vec()).unwrap()
+}
+
+// A file in the temporary directory of the tests, written with the contents
+pub fn temporary_file(name: &str, contents: &str) -> path::PathBuf {
+    let file = path::Path::new(env!("CARGO_TARGET_TMPDIR")).join(name);
+    fs::write(&file, contents).unwrap();
+    file
+}
+
+// The certificates in tests/tls: a CA and a certificate for localhost and 127.0.0.1 signed by it
+pub fn tls_file(name: &str) -> String {
+    path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("tls").join(name).to_str().unwrap().to_string()
+}
@@ -0,0 +1,61 @@
+// Author: Jorge Alarcon Alvarez
+// Email:  jorge4larcon@gmail.com
+// This module is used to check what admint makes of the replies of a misbehaving server, using
+// the faults of the simulator scenarios.
+
+extern crate admint;
+
+mod common;
+
+use common::TestServer;
+use std::time;
+
+// What a well behaved server makes admint show
+fn expected(args: &[&str]) -> String {
+    TestServer::new().run(args, &[])
+}
+
+#[test]
+fn delay_and_timeout() {
+    let server = TestServer::with_scenario("[commands.running-config]\ndelay-ms = 1500\n");
+    let started = time::Instant::now();
+    assert!(server.command_with(&["running-config"], &["--timeout", "0.3"]).send().is_none());
+    assert!(started.elapsed() < time::Duration::from_millis(1400), "{:?}", started.elapsed());
+    assert_eq!(server.run(&["running-config"], &["--timeout", "5"]), expected(&["running-config"]));
+}
+
+#[test]
+fn split() {
+    let server = TestServer::with_scenario("[commands.\"*\"]\nsplit = 3\nsplit-delay-ms = 5\n");
+    assert_eq!(server.run(&["get-index", "0", "5"], &[]), expected(&["get-index", "0", "5"]));
+    assert_eq!(server.run(&["running-config"], &["--auth", "challenge"]), expected(&["running-config"]));
+}
+
+#[test]
+fn invalid_json() {
+    let server = TestServer::with_scenario("[commands.set-capacity]\ninvalid-json = true\n");
+    let output = server.run(&["set-capacity", "30"], &[]);
+    assert!(output.starts_with("Could not parse the reply"), "{}", output);
+    assert!(output.ends_with("{\"result\":\"ok\",}"), "{}", output);
+    assert!(server.command(&["set-capacity", "30"]).send_and_parse().is_err());
+}
+
+// The clients that can not be read are shown as they came
+#[test]
+fn wrong_types() {
+    let server = TestServer::with_scenario("[commands.\"*\"]\nwrong-types = true\n");
+    let output = server.run(&["get-index", "0", "2"], &[]);
+    assert!(output.starts_with("result: ok\n2 client(s):\n"), "{}", output);
+    assert!(output.lines().skip(2).all(|line| line.contains("\"port\":\"")), "{}", output);
+    assert_eq!(server.run(&["running-config", "--get", "capacity"], &[]), "100");
+    assert_eq!(server.run(&["set-capacity", "30"], &[]), "result: ok");
+    assert_eq!(server.state.lock().unwrap().config.capacity(), 30);
+}
+
+#[test]
+fn oversize() {
+    let server = TestServer::with_scenario("[commands.get-index]\noversize = 3000\n\n[commands.running-config]\noversize = 70000\n");
+    assert_eq!(server.run(&["get-index", "0", "5"], &[]), expected(&["get-index", "0", "5"]));
+    let output = server.run(&["running-config"], &[]);
+    assert!(output.starts_with("Could not parse the reply"), "{}", &output[..100]);
+}
@@ -0,0 +1,55 @@
+// Author: Jorge Alarcon Alvarez
+// Email:  jorge4larcon@gmail.com
+// This module is used to run the mint-sim binary, checking that it validates its options like
+// admint does and serves what it was told to.
+
+extern crate admint;
+
+mod common;
+
+use admint::seed;
+use admint::server_config;
+use std::io;
+use std::io::BufRead;
+use std::path;
+use std::process;
+
+fn mint_sim(args: &[&str]) -> process::Command {
+    let mut command = process::Command::new(env!("CARGO_BIN_EXE_mint-sim"));
+    command.args(args);
+    command
+}
+
+#[test]
+fn refuses_the_values_admint_refuses() {
+    for (option, value, error) in &[("--capacity", "1", server_config::CAPACITY_ERROR), ("--drop-votes", "0", server_config::DROP_VOTES_ERROR),
+                                    ("--key", "a\u{e9}", server_config::SECRET_ERROR), ("--list-size", "70000", "is not a valid list size number"),
+                                    ("--drop-verification", "yes", "is not a valid boolean value"), ("--seed", "ten", "is not a valid unsigned number")] {
+        let output = mint_sim(&[option, value]).output().unwrap();
+        assert!(!output.status.success(), "{} {} was accepted", option, value);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains(error), "{} {}: {}", option, value, stderr);
+    }
+}
+
+#[test]
+fn serves_the_snapshot_it_wrote() {
+    let snapshot = path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("mint-sim-snapshot.json");
+    let status = mint_sim(&["--generate", "7", "--seed", "3", "--subnet", "10.1.0.0/24", "--write-snapshot", snapshot.to_str().unwrap()]).status().unwrap();
+    assert!(status.success());
+    assert_eq!(seed::load_snapshot(&snapshot).unwrap().len(), 7);
+
+    let mut child = mint_sim(&["--listen", "127.0.0.1:0", "--snapshot", snapshot.to_str().unwrap(), "--capacity", "9", "--key", common::KEY])
+        .stdout(process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut line = String::new();
+    io::BufReader::new(child.stdout.take().unwrap()).read_line(&mut line).unwrap();
+    let address = line.trim().trim_start_matches("mint-sim listening on ").to_string();
+    let running_config = common::command_at(&address, &["running-config"], &[]).send_and_interpret();
+    let clients = common::command_at(&address, &["get-index", "0", "20"], &[]).send_and_interpret();
+    child.kill().unwrap();
+    child.wait().unwrap();
+    assert!(running_config.contains("capacity: 9"), "{}", running_config);
+    assert_eq!(clients.lines().filter(|line| line.contains("10.1.0.")).count(), 7, "{}", clients);
+}
@@ -0,0 +1,198 @@
+// Author: Jorge Alarcon Alvarez
+// Email:  jorge4larcon@gmail.com
+// This module is used to run admint through stand-ins of a SOCKS5 and an HTTP CONNECT proxy that
+// tunnel to the simulator.
+
+extern crate admint;
+extern crate base64;
+
+mod common;
+
+use admint::proxy;
+use base64::Engine;
+use common::TestServer;
+use std::env;
+use std::io;
+use std::io::{
+    Read,
+    Write
+};
+use std::net;
+use std::sync;
+use std::thread;
+
+const USER: &str = "bastion";
+const PASSWORD: &str = "s3cr:t";
+
+// A proxy that accepts connections in the background, each one handled by `handshake`, which
+// returns where to tunnel to. Every target is recorded.
+struct StandIn {
+    url: String,
+    targets: sync::Arc<sync::Mutex<Vec<String>>>
+}
+
+impl StandIn {
+    fn start(scheme: &str, credentials: bool, handshake: fn(&mut net::TcpStream, bool) -> io::Result<String>) -> StandIn {
+        let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let userinfo = if credentials { format!("{}:{}@", USER, PASSWORD.replace(':', "%3A")) } else { String::new() };
+        let url = format!("{}://{}{}", scheme, userinfo, listener.local_addr().unwrap());
+        let targets = sync::Arc::new(sync::Mutex::new(Vec::new()));
+        let recorded = sync::Arc::clone(&targets);
+        thread::spawn(move || {
+            for mut client in listener.incoming().flatten() {
+                let recorded = sync::Arc::clone(&recorded);
+                thread::spawn(move || {
+                    if let Ok(target) = handshake(&mut client, credentials) {
+                        recorded.lock().unwrap().push(target.clone());
+                        if let Ok(server) = net::TcpStream::connect(&target) {
+                            tunnel(client, server);
+                        }
+                    }
+                });
+            }
+        });
+        StandIn { url, targets }
+    }
+
+    fn targets(&self) -> Vec<String> {
+        self.targets.lock().unwrap().clone()
+    }
+}
+
+// Copies the bytes both ways until the server closes
+fn tunnel(client: net::TcpStream, server: net::TcpStream) {
+    let (mut client_reader, mut server_writer) = (client.try_clone().unwrap(), server.try_clone().unwrap());
+    thread::spawn(move || {
+        let _ = io::copy(&mut client_reader, &mut server_writer);
+        let _ = server_writer.shutdown(net::Shutdown::Write);
+    });
+    let (mut server_reader, mut client_writer) = (server, client);
+    let _ = io::copy(&mut server_reader, &mut client_writer);
+    let _ = client_writer.shutdown(net::Shutdown::Both);
+}
+
+fn read_bytes(stream: &mut net::TcpStream, len: usize) -> io::Result<Vec<u8>> {
+    let mut bytes = vec![0; len];
+    stream.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn refused() -> io::Error {
+    io::Error::from(io::ErrorKind::PermissionDenied)
+}
+
+fn socks5_handshake(client: &mut net::TcpStream, credentials: bool) -> io::Result<String> {
+    let greeting = read_bytes(client, 2)?;
+    let methods = read_bytes(client, greeting[1] as usize)?;
+    let method = if credentials { 2 } else { 0 };
+    if !methods.contains(&method) {
+        client.write_all(&[5, 0xff])?;
+        return Err(refused());
+    }
+    client.write_all(&[5, method])?;
+    if credentials {
+        let user_len = read_bytes(client, 2)?[1] as usize;
+        let user = read_bytes(client, user_len)?;
+        let password_len = read_bytes(client, 1)?[0] as usize;
+        let password = read_bytes(client, password_len)?;
+        let accepted = user == USER.as_bytes() && password == PASSWORD.as_bytes();
+        client.write_all(&[1, if accepted { 0 } else { 1 }])?;
+        if !accepted {
+            return Err(refused());
+        }
+    }
+    let request = read_bytes(client, 4)?;
+    let host = match request[3] {
+        1 => {
+            let octets = read_bytes(client, 4)?;
+            net::Ipv4Addr::new(octets[0], octets[1], octets[2], octets[3]).to_string()
+        },
+        3 => {
+            let len = read_bytes(client, 1)?[0] as usize;
+            String::from_utf8(read_bytes(client, len)?).unwrap()
+        },
+        _ => return Err(refused())
+    };
+    let port = read_bytes(client, 2)?;
+    client.write_all(&[5, 0, 0, 1, 0, 0, 0, 0, 0, 0])?;
+    Ok(format!("{}:{}", host, u16::from_be_bytes([port[0], port[1]])))
+}
+
+fn http_connect_handshake(client: &mut net::TcpStream, credentials: bool) -> io::Result<String> {
+    let mut header = Vec::new();
+    while !header.ends_with(b"\r\n\r\n") {
+        header.extend(read_bytes(client, 1)?);
+    }
+    let header = String::from_utf8(header).unwrap();
+    let target = header.split_whitespace().nth(1).unwrap_or("").to_string();
+    let token = base64::engine::general_purpose::STANDARD.encode(format!("{}:{}", USER, PASSWORD));
+    if credentials && !header.contains(&format!("\r\nProxy-Authorization: Basic {}\r\n", token)) {
+        client.write_all(b"HTTP/1.1 407 Proxy Authentication Required\r\n\r\n")?;
+        return Err(refused());
+    }
+    client.write_all(b"HTTP/1.1 200 Connection established\r\n\r\n")?;
+    Ok(target)
+}
+
+// The server by name, so the proxy has to resolve it
+fn by_name(server: &TestServer) -> TestServer {
+    TestServer { address: server.address.replace("127.0.0.1", "localhost"), state: sync::Arc::clone(&server.state) }
+}
+
+fn check_tunnel(stand_in: &StandIn, server: &TestServer) {
+    assert_eq!(server.run(&["set-capacity", "60"], &["--proxy", &stand_in.url]), "result: ok");
+    assert_eq!(by_name(server).run(&["set-listsize", "6"], &["--proxy", &stand_in.url]), "result: ok");
+    assert_eq!(stand_in.targets(), vec![server.address.clone(), by_name(server).address]);
+    let state = server.state.lock().unwrap();
+    assert_eq!((state.config.capacity(), state.config.list_size()), (60, 6));
+}
+
+#[test]
+fn socks5_without_auth() {
+    check_tunnel(&StandIn::start("socks5", false, socks5_handshake), &TestServer::new());
+}
+
+#[test]
+fn socks5_with_auth() {
+    let stand_in = StandIn::start("socks5", true, socks5_handshake);
+    let server = TestServer::new();
+    check_tunnel(&stand_in, &server);
+    let wrong_password = stand_in.url.replace("s3cr", "other");
+    assert!(server.command_with(&["running-config"], &["--proxy", &wrong_password]).send().is_none());
+    let no_password = stand_in.url.replace(&format!("{}:s3cr%3At@", USER), "");
+    assert!(server.command_with(&["running-config"], &["--proxy", &no_password]).send().is_none());
+    assert_eq!(server.requests().len(), 2);
+}
+
+#[test]
+fn http_connect_without_auth() {
+    check_tunnel(&StandIn::start("http", false, http_connect_handshake), &TestServer::new());
+}
+
+#[test]
+fn http_connect_with_auth() {
+    let stand_in = StandIn::start("http", true, http_connect_handshake);
+    let server = TestServer::new();
+    check_tunnel(&stand_in, &server);
+    let no_password = stand_in.url.replace(&format!("{}:s3cr%3At@", USER), "");
+    assert!(server.command_with(&["running-config"], &["--proxy", &no_password]).send().is_none());
+    assert_eq!(server.requests().len(), 2);
+}
+
+// The proxy of ALL_PROXY is not used for the hosts of NO_PROXY, the other tests give --proxy so
+// they do not depend on the variables
+#[test]
+fn no_proxy_from_the_environment() {
+    let stand_in = StandIn::start("socks5", false, socks5_handshake);
+    let server = TestServer::new();
+    env::set_var(proxy::PROXY_VAR, &stand_in.url);
+    env::set_var(proxy::NO_PROXY_VAR, "localhost,127.0.0.1");
+    let direct = (server.run(&["set-capacity", "61"], &[]), by_name(&server).run(&["set-capacity", "62"], &[]));
+    env::set_var(proxy::NO_PROXY_VAR, "other.lab");
+    let proxied = server.run(&["set-capacity", "63"], &[]);
+    env::remove_var(proxy::PROXY_VAR);
+    env::remove_var(proxy::NO_PROXY_VAR);
+    assert_eq!(direct, (String::from("result: ok"), String::from("result: ok")));
+    assert_eq!(proxied, "result: ok");
+    assert_eq!(stand_in.targets(), vec![server.address.clone()]);
+}
@@ -0,0 +1,75 @@
+// Author: Jorge Alarcon Alvarez
+// Email:  jorge4larcon@gmail.com
+// This module is used to check that a session keeps its connection open with the servers that
+// allow it and falls back to a connection per request with the ones that do not.
+
+extern crate admint;
+
+mod common;
+
+use admint::commands;
+use admint::ipparser;
+use admint::server_config;
+use admint::session;
+use common::{
+    KEY,
+    TestServer
+};
+
+fn session(server: &TestServer) -> session::Session {
+    session::Session::new(KEY.to_string(), ipparser::ServerAddress::new_from_str(&server.address).unwrap(), commands::ConnectionOptions::default())
+}
+
+fn running_config() -> commands::Command {
+    commands::Command::Get(commands::Get::RunningConfiguration { setting: None, format: server_config::ConfigFormat::Text })
+}
+
+#[test]
+fn keeps_the_connection_open() {
+    let server = TestServer::new();
+    let mut session = session(&server);
+    assert_eq!(session.send_and_interpret(commands::Command::Set(commands::Set::Capacity(70))), "result: ok");
+    assert!(session.send_and_interpret(running_config()).contains("capacity: 70"));
+    assert!(session.send_and_parse(commands::Command::Get(commands::Get::Index { start_index: 0, end_index: 5 })).is_ok());
+    assert!(session.is_connected() && session.keeps_connection());
+    let state = server.state.lock().unwrap();
+    assert_eq!((state.connections, state.requests.len()), (1, 3));
+}
+
+#[test]
+fn falls_back_when_the_server_closes_after_the_reply() {
+    let server = TestServer::with_scenario("[commands.\"*\"]\nclose-after = 65535\n");
+    let mut session = session(&server);
+    assert!(session.send_and_interpret(running_config()).contains("capacity: 100"));
+    // The second get finds the connection closed and is sent again through a new one
+    assert!(session.send_and_interpret(running_config()).contains("capacity: 100"));
+    assert!(!session.keeps_connection());
+    assert!(!session.is_connected());
+    assert_eq!(session.send_and_interpret(commands::Command::Set(commands::Set::ListSize(6))), "result: ok");
+    let state = server.state.lock().unwrap();
+    assert_eq!((state.connections, state.requests.len(), state.config.list_size()), (3, 3, 6));
+}
+
+// The server could have run it before closing, it is not run twice
+#[test]
+fn does_not_send_again_what_is_not_a_get() {
+    let server = TestServer::with_scenario("[commands.\"*\"]\nclose-after = 65535\n");
+    let mut session = session(&server);
+    assert!(session.send_and_parse(running_config()).is_ok());
+    assert!(session.send_and_parse(commands::Command::Set(commands::Set::Capacity(70))).is_err());
+    assert!(!session.keeps_connection());
+    let state = server.state.lock().unwrap();
+    assert_eq!((state.connections, state.requests.len(), state.config.capacity()), (1, 1, 100));
+}
+
+// Every reply is taken whole from the stream, however the server writes it
+#[test]
+fn replies_split_in_many_writes() {
+    let server = TestServer::with_scenario("[commands.get-index]\nsplit = 7\nsplit-delay-ms = 2\n");
+    let mut session = session(&server);
+    for start_index in 0..3 {
+        let reply = session.send_and_parse(commands::Command::Get(commands::Get::Index { start_index, end_index: start_index + 4 })).unwrap();
+        assert_eq!(reply["clients"].as_array().map(|clients| clients.len()), Some(4));
+    }
+    assert_eq!(server.state.lock().unwrap().connections, 1);
+}
@@ -0,0 +1,425 @@
+// Author: Jorge Alarcon Alvarez
+// Email:  jorge4larcon@gmail.com
+// This module is used to run every subcommand against the simulator, checking the requests that
+// reach the server and the output the user sees. The expected ones are in tests/golden, run the
+// tests with ADMINT_BLESS=1 to write them again after an intended change.
+
+extern crate admint;
+extern crate serde_json;
+
+use admint::apply;
+use admint::auth;
+use admint::backup;
+use admint::commands;
+use admint::config;
+use admint::drift;
+use admint::ipparser;
+use admint::preflight;
+use admint::rotate;
+use admint::secret;
+use admint::seed;
+use admint::server_config;
+use admint::simulator;
+use admint::targets;
+use admint::transport;
+use std::env;
+use std::fs;
+use std::io::{
+    Read,
+    Write
+};
+use std::net;
+use std::path;
+use std::thread;
+
+mod common;
+
+use common::{
+    KEY,
+    TestServer
+};
+
+// The requests the server received and the output, compared with tests/golden/NAME.txt
+fn assert_golden(name: &str, requests: &[serde_json::Value], output: &str) {
+    let requests: Vec<String> = requests.iter().map(auth::canonical_json).collect();
+    let actual = format!("{}\n---\n{}\n", requests.join("\n"), output);
+    let golden = path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("golden").join(format!("{}.txt", name));
+    if env::var_os("ADMINT_BLESS").is_some() {
+        fs::write(&golden, &actual).unwrap();
+        return;
+    }
+    let expected = fs::read_to_string(&golden).unwrap_or_else(|e| panic!("{}: {}", golden.display(), e));
+    assert_eq!(expected, actual, "the output of {} changed", name);
+}
+
+fn check(name: &str, args: &[&str]) {
+    let server = TestServer::new();
+    let output = server.run(args, &[]);
+    assert_golden(name, &server.requests(), &output);
+}
+
+#[test]
+fn running_config() {
+    check("running-config", &["running-config"]);
+}
+
+#[test]
+fn running_config_get() {
+    check("running-config-get", &["running-config", "--get", "drop-votes"]);
+}
+
+#[test]
+fn running_config_formats() {
+    check("running-config-json", &["running-config", "--format", "json"]);
+    check("running-config-toml", &["running-config", "--get", "list-size", "--format", "toml"]);
+}
+
+// A running_config that is not a string is reported, it used to panic
+#[test]
+fn running_config_not_a_string() {
+    let matches = common::matches(&["admint", "running-config", KEY, "127.0.0.1:1"]);
+    let command = commands::BaseCommand::from_clap_matches(&matches, None).unwrap();
+    let mut memory_transport = transport::MemoryTransport::with_replies(&["{\"result\":\"ok\",\"running_config\":100}"]);
+    assert!(command.send_and_interpret_with(&mut memory_transport).starts_with("Could not parse the reply"));
+}
+
+#[test]
+fn set_capacity() {
+    check("set-capacity", &["set-capacity", "20"]);
+}
+
+#[test]
+fn set_listsize() {
+    check("set-listsize", &["set-listsize", "5"]);
+}
+
+#[test]
+fn set_dropvotes() {
+    check("set-dropvotes", &["set-dropvotes", "2"]);
+}
+
+#[test]
+fn set_dropverification() {
+    check("set-dropverification", &["set-dropverification", "false"]);
+}
+
+#[test]
+fn set_password() {
+    check("set-password", &["set-password", "clients"]);
+}
+
+#[test]
+fn set_key() {
+    let server = TestServer::new();
+    let output = server.run(&["set-key", "n3w-k3y"], &[]);
+    assert_golden("set-key", &server.requests(), &output);
+    assert_eq!(server.state.lock().unwrap().config.key(), "n3w-k3y");
+}
+
+#[test]
+fn drop() {
+    let server = TestServer::new();
+    let (_, client) = server.first_client();
+    let ip = ipparser::u32_to_ipv4(client.ipv4_addr).to_string();
+    let output = server.run(&["drop", &ip], &[]);
+    assert_golden("drop", &server.requests(), &output);
+    assert!(!server.state.lock().unwrap().clients.exists_by_ipv4(client.ipv4_addr));
+}
+
+#[test]
+fn get_mac() {
+    let server = TestServer::new();
+    let (mac, _) = server.first_client();
+    let output = server.run(&["get-mac", &mac.to_string()], &[]);
+    assert_golden("get-mac", &server.requests(), &output);
+}
+
+#[test]
+fn get_username() {
+    check("get-username", &["get-username", "comet", "0"]);
+}
+
+#[test]
+fn get_index() {
+    check("get-index", &["get-index", "0", "5"]);
+}
+
+#[test]
+fn wrong_admin_password() {
+    let server = TestServer::start(|clients_map| {
+        let mut config = server_config::ServerConfig::default();
+        config.set_key("other").unwrap();
+        simulator::SimState::new(clients_map, config)
+    });
+    let output = server.run(&["running-config"], &[]);
+    assert_golden("wrong-admin-password", &server.requests(), &output);
+}
+
+// The nonces change every time, so only the shape of the requests is checked
+#[test]
+fn challenge_auth() {
+    let server = TestServer::new();
+    let output = server.run(&["set-capacity", "20"], &["--auth", "challenge"]);
+    assert_eq!(output, "result: ok");
+    let requests = server.requests();
+    assert_eq!(requests.len(), 2);
+    assert_eq!(requests[0].get("method").and_then(|m| m.as_str()), Some("challenge"));
+    assert!(requests.iter().all(|request| request.get("password").is_none()));
+    assert_eq!(server.state.lock().unwrap().config.capacity(), 20);
+}
+
+#[test]
+fn signed_requests() {
+    let server = TestServer::start(|clients_map| common::default_state(clients_map).with_required_signatures());
+    assert_eq!(server.run(&["set-listsize", "7"], &["--sign"]), "result: ok");
+    let requests = server.requests();
+    for field in &[auth::TIMESTAMP_FIELD, auth::NONCE_FIELD, auth::SIGNATURE_FIELD] {
+        assert!(requests[0].get(field).is_some(), "{} is missing", field);
+    }
+    assert_eq!(server.state.lock().unwrap().config.list_size(), 7);
+    assert_ne!(server.run(&["set-listsize", "8"], &[]), "result: ok");
+}
+
+#[test]
+fn config_diff_exit_codes() {
+    let (first, second) = (TestServer::new(), TestServer::new());
+    let diff = |servers: &[&str]| {
+        let mut argv = vec!["admint", "config-diff", "-P", KEY];
+        argv.extend_from_slice(servers);
+        drift::config_diff(&common::matches(&argv))
+    };
+    assert_eq!(diff(&[&first.address, &second.address]), drift::NO_DRIFT);
+    second.state.lock().unwrap().config.set_list_size(3);
+    assert_eq!(diff(&[&first.address, &second.address]), drift::DRIFT);
+    // The password of the clients is a secret, it is not compared
+    second.state.lock().unwrap().config.set_list_size(20);
+    second.run(&["set-password", "other"], &[]);
+    assert_eq!(diff(&[&first.address, &second.address]), drift::NO_DRIFT);
+    first.run(&["set-key", "other"], &[]);
+    assert_eq!(diff(&[&first.address, &second.address]), drift::DIFF_ERROR);
+}
+
+// A server that answers a single request with `reply`, whatever the request is
+fn reply_once(reply: &'static str) -> String {
+    let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    thread::spawn(move || {
+        if let Ok((mut stream, _)) = listener.accept() {
+            let (mut pending, mut buffer) = (Vec::new(), [0; 1024]);
+            while transport::split_json(&mut pending, 1 << 16).is_none() {
+                match stream.read(&mut buffer) {
+                    Ok(0) | Err(_) => return,
+                    Ok(read) => pending.extend_from_slice(&buffer[..read])
+                }
+            }
+            let _ = stream.write_all(reply.as_bytes());
+        }
+    });
+    address
+}
+
+#[test]
+fn config_diff_with_a_baseline() {
+    let (first, second) = (TestServer::new(), TestServer::new());
+    let baseline = common::temporary_file("diff-baseline.toml", "capacity = 100\ndrop-votes = 3\n");
+    let diff = |servers: &[&str]| {
+        let mut argv = vec!["admint", "config-diff", "-P", KEY, "--baseline", baseline.to_str().unwrap()];
+        argv.extend_from_slice(servers);
+        drift::config_diff(&common::matches(&argv))
+    };
+    // One server is enough, the settings the baseline does not have are not compared
+    assert_eq!(diff(&[&first.address]), drift::NO_DRIFT);
+    second.state.lock().unwrap().config.set_list_size(3);
+    second.run(&["set-password", "other"], &[]);
+    assert_eq!(diff(&[&first.address, &second.address]), drift::NO_DRIFT);
+    second.state.lock().unwrap().config.set_drop_votes(2).unwrap();
+    assert_eq!(diff(&[&first.address, &second.address]), drift::DRIFT);
+    // A server that does not show every setting can not be compared
+    let missing = reply_once(r#"{"result": "ok", "running_config": "capacity: 100\nlist size: 20\npassword: \nkey: admin\n"}"#);
+    assert_eq!(diff(&[&first.address, &missing]), drift::DIFF_ERROR);
+    let invalid = common::temporary_file("diff-invalid-baseline.toml", "capacity = 1\n");
+    let argv = vec!["admint", "config-diff", "-P", KEY, "--baseline", invalid.to_str().unwrap(), &first.address];
+    assert_eq!(drift::config_diff(&common::matches(&argv)), drift::DIFF_ERROR);
+}
+
+#[test]
+fn apply_sends_only_the_changes() {
+    let server = TestServer::new();
+    let desired = common::temporary_file("apply-desired.toml", "capacity = 150\nlist-size = 20\ndrop-verification = false\n");
+    let argv = vec!["admint", "apply", desired.to_str().unwrap(), &server.address, "-P", KEY, "--yes"];
+    assert_eq!(apply::apply(&common::matches(&argv)), 0);
+    let sets: Vec<String> = server.requests().iter()
+        .filter_map(|request| request.get("what").and_then(|what| what.as_str()).map(String::from))
+        .collect();
+    assert_eq!(sets, vec!["capacity", "drop_verification"]);
+    let state = server.state.lock().unwrap();
+    assert_eq!((state.config.capacity(), state.config.drop_verification()), (150, false));
+}
+
+#[test]
+fn plan_outcome() {
+    let server = TestServer::new();
+    let target = targets::Target::from_base_command(&server.command(&["running-config"]));
+    let desired = server_config::DesiredConfig { capacity: Some(150), drop_votes: Some(2), ..server_config::DesiredConfig::default() };
+    let plan = apply::Plan::new(&target.running_config().unwrap(), &desired);
+    assert!(plan.render().ends_with("  warning: the clients with 2 or more drop votes will be dropped and can not be brought back"), "{}", plan.render());
+    let clients = TestServer::clients();
+    let to_drop = clients.range(0, clients.len()).iter().filter(|(_, client)| client.drop_votes >= 2).count();
+    assert!(to_drop > 0);
+    let outcome = plan.execute(&target);
+    assert!(outcome.error.is_none());
+    assert_eq!(outcome.changed(), vec![server_config::Setting::Capacity, server_config::Setting::DropVotes]);
+    assert_eq!(outcome.applied[0].to_string(), "capacity: 100 -> 150 ok");
+    assert_eq!(outcome.applied[1].to_string(), format!("drop-votes: 3 -> 2 ok, {} client(s) dropped", to_drop));
+    assert!(!apply::Plan::new(&target.running_config().unwrap(), &desired).render().contains("warning"));
+}
+
+#[test]
+fn transactional_apply_rolls_back() {
+    let server = TestServer::with_scenario("[commands.set-dropverification]\nclose-after = 0\n");
+    let desired = common::temporary_file("apply-transactional.toml", "capacity = 150\nlist-size = 30\ndrop-verification = false\n");
+    let argv = vec!["admint", "apply", desired.to_str().unwrap(), &server.address, "-P", KEY, "--yes", "--transactional"];
+    assert_eq!(apply::apply(&common::matches(&argv)), 1);
+    // The simulator sets drop_verification before closing the connection, the running config
+    // tells it changed too
+    let state = server.state.lock().unwrap();
+    assert_eq!((state.config.capacity(), state.config.list_size(), state.config.drop_verification()), (100, 20, true));
+}
+
+#[test]
+fn backup_and_restore() {
+    let (source, destination) = (TestServer::new(), TestServer::new());
+    source.run(&["set-capacity", "64"], &[]);
+    let directory = path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("backups");
+    let _ = fs::remove_dir_all(&directory);
+    fs::create_dir_all(&directory).unwrap();
+    let argv = vec!["admint", "backup", &source.address, "-P", KEY, "--clients", "-o", directory.to_str().unwrap()];
+    assert_eq!(backup::backup(&common::matches(&argv)), 0);
+    let file = fs::read_dir(&directory).unwrap().next().unwrap().unwrap().path();
+    let saved = backup::Backup::load(&file).unwrap();
+    assert_eq!(saved.clients.map(|clients| clients.len()), Some(12));
+    assert!(!fs::read_to_string(&file).unwrap().contains("\"key\""));
+
+    let argv = vec!["admint", "restore", file.to_str().unwrap(), &destination.address, "-P", KEY, "-y"];
+    assert_eq!(backup::restore(&common::matches(&argv)), 0);
+    assert_eq!(destination.state.lock().unwrap().config.capacity(), 64);
+}
+
+// Pages shorter than asked for are not the last one
+#[test]
+fn backup_pages_until_an_empty_page() {
+    let scenario = simulator::Scenario::from_toml_str("[commands.get-index]\nmax-clients = 7\n").unwrap();
+    let clients_map = seed::generate(&seed::SeedOptions { count: 250, seed: 40, ..seed::SeedOptions::default() }).unwrap();
+    let server = TestServer::serve(simulator::Simulator::bind("127.0.0.1:0", common::default_state(clients_map).with_scenario(scenario)).unwrap());
+    let target = targets::Target::from_base_command(&server.command(&["running-config"]));
+    let fetched = target.clients().unwrap();
+    assert_eq!(seed::to_snapshot(&fetched), seed::to_snapshot(&server.state.lock().unwrap().clients));
+    assert_eq!(server.state.lock().unwrap().connections, 1);
+}
+
+#[test]
+fn backup_never_overwrites() {
+    let server = TestServer::new();
+    let directory = path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("backups-overwrite");
+    let _ = fs::remove_dir_all(&directory);
+    fs::create_dir_all(&directory).unwrap();
+    let argv = vec!["admint", "backup", &server.address, "-P", KEY, "-o", directory.to_str().unwrap()];
+    assert_eq!(backup::backup(&common::matches(&argv)), 0);
+    // The names only differ in the milliseconds
+    std::thread::sleep(std::time::Duration::from_millis(5));
+    assert_eq!(backup::backup(&common::matches(&argv)), 0);
+    assert_eq!(fs::read_dir(&directory).unwrap().count(), 2);
+    let file = fs::read_dir(&directory).unwrap().next().unwrap().unwrap().path();
+    assert!(backup::Backup::load(&file).unwrap().write(&file).unwrap_err().starts_with("Could not write"));
+}
+
+#[test]
+fn preflight_refuses_conflicts() {
+    let server = TestServer::new();
+    let e = preflight::check(&server.command(&["set-capacity", "10"]), false).unwrap_err();
+    assert!(e.contains("12 clients are connected but the capacity would be 10"), "{}", e);
+    assert!(e.contains("the list size 20 would be larger than the capacity 10"), "{}", e);
+    assert!(preflight::check(&server.command(&["set-capacity", "10"]), true).is_ok());
+    assert!(preflight::check(&server.command(&["set-capacity", "50"]), false).is_ok());
+    let e = preflight::check(&server.command(&["set-listsize", "120"]), false).unwrap_err();
+    assert!(e.contains("the list size 120 would be larger than the capacity 100"), "{}", e);
+    let forced = common::matches(&["admint", "set-listsize", "--force", "120", KEY, &server.address]);
+    assert!(admint::RunOptions::from_clap_matches(&forced).force);
+    assert!(preflight::check(&commands::BaseCommand::from_clap_matches(&forced, None).unwrap(), true).is_ok());
+    // Only reads, nothing is set
+    assert!(server.requests().iter().all(|request| request["method"] == "get"));
+    // The clients are only needed for a new capacity
+    let clients_requests = server.requests().iter().filter(|request| request["how"] == "index").count();
+    assert!(clients_requests > 0);
+    preflight::check(&server.command(&["set-listsize", "120"]), false).unwrap_err();
+    assert_eq!(server.requests().iter().filter(|request| request["how"] == "index").count(), clients_requests);
+}
+
+// Not knowing the clients is no conflict, the set runs
+#[test]
+fn preflight_without_the_live_state() {
+    let server = TestServer::with_scenario("[commands.get-index]\ninvalid-json = true\n");
+    assert!(preflight::check(&server.command(&["set-capacity", "10"]), false).is_ok());
+    let e = preflight::check(&server.command(&["set-listsize", "120"]), false).unwrap_err();
+    assert!(e.contains("the list size 120 would be larger than the capacity 100"), "{}", e);
+}
+
+#[test]
+fn rotate_key_when_the_reply_is_lost() {
+    let server = TestServer::with_scenario("[commands.set-key]\nclose-after = 0\n");
+    let profiles = path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("rotate-profiles.toml");
+    fs::write(&profiles, format!("# The lab servers\n[profiles.lab]\nserver-address = \"{}\"\nadmin-password = \"{}\" # rotated\n\n[profiles.other]\nadmin-password = \"{}\"\n",
+                                 server.address, KEY, KEY)).unwrap();
+    let argv = vec!["admint", "--config", profiles.to_str().unwrap(), "rotate-key", "lab"];
+    assert_eq!(rotate::rotate_key(&common::matches(&argv)), rotate::ROTATED);
+    let key = server.state.lock().unwrap().config.key().to_string();
+    assert_ne!(key, KEY);
+    let contents = fs::read_to_string(&profiles).unwrap();
+    assert!(contents.starts_with("# The lab servers\n"));
+    let stored = config::Profiles::from_toml_str(&contents).unwrap();
+    assert_eq!(stored.get("lab").unwrap().admin_password.as_deref(), Some(key.as_str()));
+    assert_eq!(stored.get("other").unwrap().admin_password.as_deref(), Some(KEY));
+}
+
+#[test]
+fn rotate_outcomes() {
+    let target = |server: &TestServer| targets::Target::from_base_command(&server.command(&["running-config"]));
+    // Too long for a key, the server refuses it and says why
+    let too_long = "k".repeat(40);
+    let server = TestServer::new();
+    match rotate::rotate(&target(&server), &too_long) {
+        rotate::Outcome::Refused(e) => assert!(e.starts_with("error code "), "{}", e),
+        outcome => panic!("{:?}", outcome)
+    }
+    assert_eq!(server.state.lock().unwrap().config.key(), KEY);
+    // The refusal is not readable, only the old key tells what happened
+    let server = TestServer::with_scenario("[commands.set-key]\ninvalid-json = true\n");
+    assert_eq!(rotate::rotate(&target(&server), &too_long), rotate::Outcome::NotRotated);
+    // The key changed but the running config can not be read with any of them
+    let server = TestServer::with_scenario("[commands.set-key]\nclose-after = 0\n\n[commands.running-config]\ninvalid-json = true\n");
+    assert_eq!(rotate::rotate(&target(&server), "new-key"), rotate::Outcome::Unknown);
+    assert_eq!(server.state.lock().unwrap().config.key(), "new-key");
+}
+
+// The generated secrets are what is set, the generation itself is tested in secret
+#[test]
+fn generated_secrets_are_set() {
+    let server = TestServer::new();
+    let profile = config::Profile {
+        server_address: Some(server.address.clone()),
+        admin_password: Some(KEY.to_string()),
+        ..config::Profile::default()
+    };
+    let matches = common::matches(&["admint", "set-key", "--generate"]);
+    assert_eq!(commands::BaseCommand::from_clap_matches(&matches, Some(&profile)).unwrap().send_and_interpret(), "result: ok");
+    let key = server.state.lock().unwrap().config.key().to_string();
+    assert_eq!(key.len(), secret::DEFAULT_KEY_LENGTH);
+    assert!(key.chars().all(|c| c.is_ascii_alphanumeric()));
+
+    let profile = config::Profile { admin_password: Some(key), ..profile.clone() };
+    let matches = common::matches(&["admint", "set-password", "--generate", "--alphabet", "hex", "--length", "20"]);
+    assert_eq!(commands::BaseCommand::from_clap_matches(&matches, Some(&profile)).unwrap().send_and_interpret(), "result: ok");
+    let password = server.state.lock().unwrap().config.password().to_string();
+    assert_eq!(password.len(), 20);
+    assert!(password.chars().all(|c| secret::HEX.contains(c)));
+}
@@ -0,0 +1,54 @@
+// Author: Jorge Alarcon Alvarez
+// Email:  jorge4larcon@gmail.com
+// This module is used to check which server certificates admint trusts, against a simulator
+// serving the certificates in tests/tls.
+
+extern crate admint;
+
+mod common;
+
+use admint::tls;
+use common::TestServer;
+
+// SHA-256 of tests/tls/server.pem
+const SERVER_FINGERPRINT: &str = "FF:C2:0C:B2:17:86:8F:7F:5D:09:E4:FF:54:60:2D:F9:C5:0A:D6:F5:7F:16:92:A1:A5:01:B2:B0:65:B0:48:0C";
+// Not the SHA-256 of any certificate in tests/tls
+const OTHER_FINGERPRINT: &str = "00:11:22:33:44:55:66:77:88:99:AA:BB:CC:DD:EE:FF:00:11:22:33:44:55:66:77:88:99:AA:BB:CC:DD:EE:FF";
+
+#[test]
+fn trusted_by_the_ca_file() {
+    let server = TestServer::with_tls();
+    let ca_file = common::tls_file("ca.pem");
+    assert_eq!(server.run(&["set-capacity", "40"], &["--tls-ca-file", &ca_file]), "result: ok");
+    assert_eq!(server.state.lock().unwrap().config.capacity(), 40);
+    // The certificate is checked against the name too
+    assert!(server.command_with(&["set-capacity", "50"], &["--tls-ca-file", &ca_file, "--tls-server-name", "mint.example.com"]).send().is_none());
+    assert_eq!(server.state.lock().unwrap().config.capacity(), 40);
+}
+
+// Signed by a CA that is not among the Mozilla roots
+#[test]
+fn not_trusted_without_the_ca_file() {
+    let server = TestServer::with_tls();
+    assert!(server.command_with(&["running-config"], &["--tls"]).send().is_none());
+    assert!(server.requests().is_empty());
+}
+
+#[test]
+fn pinned_fingerprint_that_matches() {
+    let server = TestServer::with_tls();
+    assert_eq!(tls::fingerprint_to_string(&tls::str_to_fingerprint(SERVER_FINGERPRINT).unwrap()), SERVER_FINGERPRINT);
+    // The name is not checked, only the fingerprint
+    assert_eq!(server.run(&["set-listsize", "9"], &["--tls-fingerprint", SERVER_FINGERPRINT, "--tls-server-name", "mint.example.com"]), "result: ok");
+    assert_eq!(server.state.lock().unwrap().config.list_size(), 9);
+}
+
+#[test]
+fn pinned_fingerprint_that_does_not_match() {
+    let server = TestServer::with_tls();
+    // Even when the CA would trust it
+    let command = server.command_with(&["set-listsize", "9"], &["--tls-fingerprint", OTHER_FINGERPRINT, "--tls-ca-file", &common::tls_file("ca.pem")]);
+    assert!(command.send().is_none());
+    assert!(server.requests().is_empty());
+    assert_eq!(server.state.lock().unwrap().config.list_size(), 20);
+}
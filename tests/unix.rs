@@ -0,0 +1,28 @@
+// Author: Jorge Alarcon Alvarez
+// Email:  jorge4larcon@gmail.com
+// This module is used to run admint against a simulator listening on a Unix socket.
+
+#![cfg(unix)]
+
+extern crate admint;
+
+mod common;
+
+use admint::simulator;
+use common::TestServer;
+use std::fs;
+use std::path;
+use std::process;
+
+#[test]
+fn unix_socket_round_trip() {
+    let socket_path = path::Path::new(env!("CARGO_TARGET_TMPDIR")).join(format!("mint-{}.sock", process::id()));
+    let _ = fs::remove_file(&socket_path);
+    let address = format!("unix:{}", socket_path.display());
+    let server = TestServer::serve(simulator::Simulator::bind(&address, common::default_state(TestServer::clients())).unwrap());
+    assert_eq!(server.address, address);
+    assert_eq!(server.run(&["set-capacity", "33"], &[]), "result: ok");
+    assert!(server.run(&["running-config"], &[]).contains("capacity: 33"));
+    assert_eq!(server.requests().len(), 2);
+    let _ = fs::remove_file(&socket_path);
+}